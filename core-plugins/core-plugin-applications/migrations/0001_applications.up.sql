@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS applications (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    -- the .desktop entry or the .app bundle
+    path TEXT UNIQUE NOT NULL,
+    -- the command line of .desktop entries, NULL for bundles, which are opened instead
+    exec TEXT,
+    icon TEXT,
+    generic_name TEXT,
+    -- `;` separated, like in .desktop entries
+    keywords TEXT NOT NULL DEFAULT '',
+    launch_count INTEGER NOT NULL DEFAULT 0,
+    -- unix time in milliseconds
+    last_launched INTEGER,
+    -- unix time in milliseconds of the last write, rows an index didn't write were uninstalled
+    indexed_at INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,8 @@
+ALTER TABLE applications ADD COLUMN launch_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE applications ADD COLUMN last_launched INTEGER;
+UPDATE applications
+SET launch_count = launches.launch_count, last_launched = launches.last_launched
+FROM launches
+WHERE launches.application_id = applications.application_id;
+ALTER TABLE applications DROP COLUMN application_id;
+DROP TABLE IF EXISTS launches;
@@ -0,0 +1,19 @@
+-- launches are counted apart from the indexed applications, so an index missing an application,
+-- e.g. while the directory it's installed in isn't mounted, doesn't lose its count
+CREATE TABLE IF NOT EXISTS launches (
+    -- the desktop file id, e.g. `firefox.desktop`, or the file name of the `.app` bundle
+    application_id TEXT NOT NULL PRIMARY KEY,
+    launch_count INTEGER NOT NULL DEFAULT 0,
+    -- unix time in milliseconds
+    last_launched INTEGER
+);
+-- the id of an entry in the top level of an applications directory is its file name
+INSERT INTO launches (application_id, launch_count, last_launched)
+SELECT replace(path, rtrim(path, replace(path, '/', '')), ''), max(launch_count), max(last_launched)
+FROM applications
+WHERE launch_count > 0
+GROUP BY 1;
+ALTER TABLE applications DROP COLUMN launch_count;
+ALTER TABLE applications DROP COLUMN last_launched;
+-- filled in by the next index
+ALTER TABLE applications ADD COLUMN application_id TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,57 @@
+use crate::desktop;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Application {
+    /// The desktop file id, e.g. `firefox.desktop`, or the file name of the `.app` bundle.
+    /// Launches are counted by it.
+    pub id: String,
+    pub name: String,
+    /// The `.desktop` entry or the `.app` bundle
+    pub path: String,
+    /// The command line without field codes, `None` for bundles, which are opened instead
+    pub exec: Option<String>,
+    /// An icon name from the icon theme or an absolute path
+    pub icon: Option<String>,
+    pub generic_name: Option<String>,
+    pub keywords: Vec<String>,
+    /// How often the application was launched from the launcher
+    pub launch_count: i64,
+}
+
+impl Application {
+    /// What `Action::LaunchApplication` carries, the command to run or the bundle to open
+    pub fn target(&self) -> &str {
+        self.exec.as_deref().unwrap_or(&self.path)
+    }
+}
+
+/// Every installed application, the bundles in `/Applications` on macOS and the `.desktop`
+/// entries on Linux
+pub fn index() -> Vec<Application> {
+    let mut applications = bundles();
+    applications.extend(desktop::index_desktop_entries());
+    applications
+}
+
+fn bundles() -> Vec<Application> {
+    WalkDir::new("/Applications/")
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "app"))
+        .filter_map(|entry| {
+            Some(Application {
+                id: entry.file_name().to_string_lossy().to_string(),
+                name: entry.path().file_stem()?.to_string_lossy().to_string(),
+                path: entry.path().to_string_lossy().to_string(),
+                exec: None,
+                icon: None,
+                generic_name: None,
+                keywords: vec![],
+                launch_count: 0,
+            })
+        })
+        .collect()
+}
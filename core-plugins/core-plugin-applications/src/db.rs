@@ -0,0 +1,128 @@
+use crate::application::Application;
+use directories::BaseDirs;
+use sqlx::{sqlite::SqliteConnectOptions, Row};
+
+const UPSERT_APPLICATION: &str = "INSERT INTO applications (application_id, name, path, exec, icon, generic_name, keywords, indexed_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT(path) DO UPDATE SET application_id = excluded.application_id, name = excluded.name, exec = excluded.exec, icon = excluded.icon, generic_name = excluded.generic_name, keywords = excluded.keywords, indexed_at = excluded.indexed_at";
+
+#[derive(Clone, Default)]
+pub struct Db {
+    pool: Option<sqlx::SqlitePool>,
+}
+
+impl Db {
+    pub fn init(&mut self, runtime: &tokio::runtime::Handle) -> plugin_api::Result<()> {
+        let path = BaseDirs::new()
+            .map(|base_dirs| base_dirs.home_dir().join(".config/fin/applications.sqlite"))
+            .ok_or_else(|| {
+                plugin_api::Error::other("Unable to determine the applications database path")
+            })?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        runtime.block_on(async {
+            let options = SqliteConnectOptions::new()
+                .filename(&path)
+                .create_if_missing(true);
+            let pool = sqlx::SqlitePool::connect_with(options)
+                .await
+                .map_err(plugin_api::Error::other)?;
+            sqlx::migrate!().run(&pool).await.map_err(|error| {
+                plugin_api::Error::other(format!("Migrations failed: {}", error))
+            })?;
+            self.pool = Some(pool);
+            Ok(())
+        })
+    }
+
+    fn pool(&self) -> plugin_api::Result<&sqlx::SqlitePool> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| plugin_api::Error::other("Applications database is not connected"))
+    }
+
+    /// The applications found by the last index, most launched first
+    pub async fn applications(&self) -> plugin_api::Result<Vec<Application>> {
+        let records = sqlx::query(
+            "SELECT applications.*, coalesce(launches.launch_count, 0) AS launch_count FROM applications LEFT JOIN launches USING (application_id) ORDER BY launch_count DESC, launches.last_launched DESC, name",
+        )
+        .fetch_all(self.pool()?)
+        .await
+        .map_err(plugin_api::Error::other)?;
+        Ok(records
+            .iter()
+            .map(|record| Application {
+                id: record.get("application_id"),
+                name: record.get("name"),
+                path: record.get("path"),
+                exec: record.get("exec"),
+                icon: record.get("icon"),
+                generic_name: record.get("generic_name"),
+                keywords: record
+                    .get::<&str, _>("keywords")
+                    .split(';')
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(|keyword| keyword.to_string())
+                    .collect(),
+                launch_count: record.get("launch_count"),
+            })
+            .collect())
+    }
+
+    /// Stores the applications of a fresh index, dropping the ones that were uninstalled. An
+    /// index that found nothing is taken to have failed and keeps the stored applications.
+    pub async fn replace_applications(
+        &self,
+        applications: &[Application],
+    ) -> plugin_api::Result<()> {
+        if applications.is_empty() {
+            return Ok(());
+        }
+        let indexed_at = now_millis();
+        let mut tx = self
+            .pool()?
+            .begin()
+            .await
+            .map_err(plugin_api::Error::other)?;
+        for application in applications {
+            sqlx::query(UPSERT_APPLICATION)
+                .bind(&application.id)
+                .bind(&application.name)
+                .bind(&application.path)
+                .bind(&application.exec)
+                .bind(&application.icon)
+                .bind(&application.generic_name)
+                .bind(application.keywords.join(";"))
+                .bind(indexed_at)
+                .execute(&mut *tx)
+                .await
+                .map_err(plugin_api::Error::other)?;
+        }
+        sqlx::query("DELETE FROM applications WHERE indexed_at < $1")
+            .bind(indexed_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(plugin_api::Error::other)?;
+        tx.commit().await.map_err(plugin_api::Error::other)
+    }
+
+    /// Counts a launch of the application with the given id
+    pub async fn record_launch(&self, application_id: &str) -> plugin_api::Result<()> {
+        sqlx::query(
+            "INSERT INTO launches (application_id, launch_count, last_launched) VALUES ($1, 1, $2) ON CONFLICT(application_id) DO UPDATE SET launch_count = launch_count + 1, last_launched = excluded.last_launched",
+        )
+        .bind(application_id)
+        .bind(now_millis())
+        .execute(self.pool()?)
+        .await
+        .map_err(plugin_api::Error::other)?;
+        Ok(())
+    }
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
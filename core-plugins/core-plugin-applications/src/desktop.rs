@@ -0,0 +1,369 @@
+use crate::application::Application;
+use directories::BaseDirs;
+use plugin_api::executor;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Applications listed by freedesktop `.desktop` entries, as shown in application menus
+pub fn index_desktop_entries() -> Vec<Application> {
+    let locales = locales();
+    let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(|desktop| desktop.to_string())
+        .collect();
+
+    // the first entry with an id wins, even a hidden one, which is how users remove entries
+    let mut seen = HashSet::new();
+    let mut applications = vec![];
+    for directory in application_directories() {
+        for file in WalkDir::new(&directory)
+            .min_depth(1)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|file| file.path().extension().is_some_and(|ext| ext == "desktop"))
+        {
+            let Ok(relative) = file.path().strip_prefix(&directory) else {
+                continue;
+            };
+            let id = relative.to_string_lossy().replace('/', "-");
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(file.path()) else {
+                continue;
+            };
+            applications.extend(parse_desktop_entry(
+                &id,
+                file.path(),
+                &content,
+                &locales,
+                &desktops,
+            ));
+        }
+    }
+    applications
+}
+
+/// `$XDG_DATA_HOME/applications` followed by the `applications` directories in `$XDG_DATA_DIRS`,
+/// in order of precedence
+fn application_directories() -> Vec<PathBuf> {
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| BaseDirs::new().map(|base_dirs| base_dirs.home_dir().join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|paths| !paths.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|directory| directory.join("applications"))
+        .collect()
+}
+
+/// Locale suffixes of localized keys to look for in the user's locale
+fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale_suffixes(&locale)
+}
+
+/// Locale suffixes of localized keys to look for, most specific first, e.g. `de_DE@euro`,
+/// `de_DE`, `de@euro` and `de` for `de_DE.UTF-8@euro`
+fn locale_suffixes(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return vec![];
+    }
+    let (language, country) = match locale.split_once('_') {
+        Some((language, country)) => (language, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut locales = vec![];
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        locales.push(format!("{language}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        locales.push(format!("{language}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        locales.push(format!("{language}@{modifier}"));
+    }
+    locales.push(language.to_string());
+    locales
+}
+
+/// Reads the entry with the given desktop file id, e.g. `org.kde.dolphin.desktop`
+fn parse_desktop_entry(
+    id: &str,
+    path: &Path,
+    content: &str,
+    locales: &[String],
+    desktops: &[String],
+) -> Option<Application> {
+    let mut fields: HashMap<&str, &str> = HashMap::new();
+    let mut in_desktop_entry = false;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            // other groups, e.g. `[Desktop Action new-window]`, describe secondary actions
+            in_desktop_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=').filter(|_| in_desktop_entry) {
+            fields.entry(key.trim()).or_insert(value.trim());
+        }
+    }
+
+    let get = |key: &str| fields.get(key).copied();
+    let localized = |key: &str| {
+        locales
+            .iter()
+            .find_map(|locale| get(&format!("{key}[{locale}]")))
+            .or_else(|| get(key))
+    };
+    let shown_in = |key: &str| {
+        get(key).map(|value| {
+            split_list(value)
+                .iter()
+                .any(|desktop| desktops.contains(desktop))
+        })
+    };
+    if get("Type") != Some("Application")
+        || get("NoDisplay") == Some("true")
+        || get("Hidden") == Some("true")
+        || shown_in("OnlyShowIn") == Some(false)
+        || shown_in("NotShowIn") == Some(true)
+    {
+        return None;
+    }
+
+    Some(Application {
+        id: id.to_string(),
+        name: unescape(localized("Name")?),
+        path: path.to_string_lossy().to_string(),
+        exec: Some(exec_command(&unescape(get("Exec")?))),
+        icon: get("Icon").map(unescape),
+        generic_name: localized("GenericName").map(unescape),
+        keywords: localized("Keywords").map(split_list).unwrap_or_default(),
+        launch_count: 0,
+    })
+}
+
+/// Removes the field codes from an `Exec` line, which stand for the files or URLs an application
+/// is opened with. Nothing is passed when launching it from the launcher, so arguments that only
+/// held field codes are dropped and the others are kept as they were quoted.
+fn exec_command(exec: &str) -> String {
+    let arguments: Vec<String> = executor::split_command_line(exec)
+        .iter()
+        .filter_map(|argument| strip_field_codes(argument))
+        .collect();
+    executor::join_command_line(&arguments)
+}
+
+/// The argument without field codes, `None` if nothing but field codes was left out of it
+fn strip_field_codes(argument: &str) -> Option<String> {
+    let mut stripped = String::new();
+    let mut had_field_code = false;
+    let mut characters = argument.chars();
+    while let Some(c) = characters.next() {
+        if c != '%' {
+            stripped.push(c);
+            continue;
+        }
+        match characters.next() {
+            Some('%') => stripped.push('%'),
+            _ => had_field_code = true,
+        }
+    }
+    (!had_field_code || !stripped.is_empty()).then_some(stripped)
+}
+
+/// Splits a `;` separated list, where `\;` is a literal `;`
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![];
+    let mut item = String::new();
+    let mut characters = value.chars();
+    while let Some(c) = characters.next() {
+        match c {
+            '\\' => match characters.next() {
+                Some(';') => item.push(';'),
+                Some(next) => {
+                    item.push('\\');
+                    item.push(next);
+                }
+                None => item.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut item))),
+            c => item.push(c),
+        }
+    }
+    items.push(unescape(&item));
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// Resolves the escape sequences allowed in string values
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut characters = value.chars();
+    while let Some(c) = characters.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match characters.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(next) => unescaped.push(next),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Option<Application> {
+        parse_desktop_entry(
+            "test.desktop",
+            Path::new("/usr/share/applications/test.desktop"),
+            content,
+            &locale_suffixes("de_DE.UTF-8"),
+            &["GNOME".to_string()],
+        )
+    }
+
+    fn arguments(exec: &str) -> Vec<String> {
+        let application = parse(&format!(
+            "[Desktop Entry]\nType=Application\nName=Test\nExec={exec}\n"
+        ))
+        .unwrap();
+        executor::split_command_line(&application.exec.unwrap())
+    }
+
+    #[test]
+    fn strips_field_codes() {
+        assert_eq!(arguments("firefox %u"), ["firefox"]);
+        assert_eq!(
+            arguments("gimp-2.10 %U --new-instance"),
+            ["gimp-2.10", "--new-instance"]
+        );
+        assert_eq!(
+            arguments("vlc --started-from-file \"%f\""),
+            ["vlc", "--started-from-file"]
+        );
+        assert_eq!(arguments("app --icon=%i%c"), ["app", "--icon="]);
+    }
+
+    #[test]
+    fn keeps_percent_signs_and_empty_arguments() {
+        assert_eq!(arguments("printf 100%%"), ["printf", "100%"]);
+        assert_eq!(arguments("app \"\" %F"), ["app", ""]);
+    }
+
+    #[test]
+    fn keeps_quoted_arguments_as_they_are() {
+        assert_eq!(arguments("\"/opt/My  App/app\" %F"), ["/opt/My  App/app"]);
+        assert_eq!(
+            arguments("sh -c \"echo  two  spaces\""),
+            ["sh", "-c", "echo  two  spaces"]
+        );
+        // `\\"` in the file is an escaped quote once string escapes are resolved
+        assert_eq!(
+            arguments("echo \"a \\\\\"quoted\\\\\" word\""),
+            ["echo", "a \"quoted\" word"]
+        );
+        assert_eq!(arguments("echo\\sspaced"), ["echo", "spaced"]);
+    }
+
+    #[test]
+    fn reads_localized_values() {
+        let application = parse(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=Files\n\
+             Name[de]=Dateien\n\
+             GenericName=File Manager\n\
+             GenericName[de_DE]=Dateimanager\n\
+             Keywords=folder;manager;semi\\;colon;\n\
+             Icon=org.gnome.Nautilus\n\
+             Exec=nautilus --new-window %U\n",
+        )
+        .unwrap();
+        assert_eq!(application.name, "Dateien");
+        assert_eq!(application.generic_name.as_deref(), Some("Dateimanager"));
+        assert_eq!(application.keywords, ["folder", "manager", "semi;colon"]);
+        assert_eq!(application.icon.as_deref(), Some("org.gnome.Nautilus"));
+        assert_eq!(application.path, "/usr/share/applications/test.desktop");
+    }
+
+    #[test]
+    fn only_reads_the_desktop_entry_group() {
+        let application = parse(
+            "# comment\n\
+             [Desktop Entry]\n\
+             Type=Application\n\
+             Name=Firefox\n\
+             Exec=firefox %u\n\
+             [Desktop Action new-private-window]\n\
+             Name=New Private Window\n\
+             Exec=firefox --private-window %u\n",
+        )
+        .unwrap();
+        assert_eq!(application.name, "Firefox");
+        assert_eq!(application.exec.as_deref(), Some("firefox"));
+    }
+
+    #[test]
+    fn skips_entries_not_meant_to_be_shown() {
+        let entry = |extra: &str| {
+            parse(&format!(
+                "[Desktop Entry]\nType=Application\nName=Test\nExec=test\n{extra}\n"
+            ))
+        };
+        assert!(entry("").is_some());
+        assert!(entry("NoDisplay=true").is_none());
+        assert!(entry("Hidden=true").is_none());
+        assert!(entry("OnlyShowIn=KDE;").is_none());
+        assert!(entry("OnlyShowIn=KDE;GNOME;").is_some());
+        assert!(entry("NotShowIn=GNOME;").is_none());
+        assert!(
+            parse("[Desktop Entry]\nType=Link\nName=Test\nURL=https://example.com\n").is_none()
+        );
+        assert!(parse("[Desktop Entry]\nType=Application\nExec=test\n").is_none());
+    }
+
+    #[test]
+    fn orders_locale_suffixes_by_specificity() {
+        assert_eq!(
+            locale_suffixes("de_DE.UTF-8@euro"),
+            ["de_DE@euro", "de_DE", "de@euro", "de"]
+        );
+        assert_eq!(locale_suffixes("fr"), ["fr"]);
+        assert!(locale_suffixes("C.UTF-8").is_empty());
+        assert!(locale_suffixes("").is_empty());
+    }
+}
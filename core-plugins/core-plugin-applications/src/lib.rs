@@ -0,0 +1,260 @@
+mod application;
+mod db;
+mod desktop;
+
+use application::Application;
+use plugin_api::{
+    config::{Config, ConfigField, ConfigKind, ConfigValue},
+    fuzzy, ranking, Plugin,
+};
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const RESULT_LIMIT: usize = 20;
+/// How often installed applications are indexed again
+const INDEX_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Keyword matches count less than name matches, `browser` shouldn't outrank a browser named so
+const KEYWORD_WEIGHT: f32 = 0.7;
+/// Boost of frequently launched applications, reached halfway after `LAUNCHES_TO_HALF` launches
+const LAUNCH_WEIGHT: f32 = 0.2;
+const LAUNCHES_TO_HALF: f32 = 5.0;
+
+/// A configured shortcut, e.g. `ff` for Firefox
+#[derive(Clone, Debug)]
+struct Alias {
+    alias: String,
+    application: String,
+}
+
+#[derive(Clone, Default)]
+pub struct ApplicationsPlugin {
+    db: db::Db,
+    applications: Vec<Application>,
+    aliases: Vec<Alias>,
+    runtime: Option<tokio::runtime::Handle>,
+    last_indexed: Option<Instant>,
+}
+
+impl Plugin for ApplicationsPlugin {
+    fn init(
+        &mut self,
+        loaded_plugin: Arc<Mutex<plugin_api::LoadedPlugin>>,
+    ) -> plugin_api::Result<()> {
+        let (runtime, config) = {
+            let loaded_plugin = plugin_api::lock(&loaded_plugin);
+            (loaded_plugin.runtime.clone(), loaded_plugin.config.clone())
+        };
+        self.aliases = Self::aliases(&config);
+        self.db.init(&runtime)?;
+        // searchable right away, indexing catches up in the background
+        self.applications = runtime.block_on(self.db.applications())?;
+        self.runtime = Some(runtime);
+        println!("Applications plugin initialized!");
+        Ok(())
+    }
+
+    fn start(&mut self) -> plugin_api::Result<()> {
+        if self
+            .last_indexed
+            .is_some_and(|indexed| indexed.elapsed() < INDEX_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_indexed = Some(Instant::now());
+        let runtime = self.runtime()?;
+        let applications = application::index();
+        self.applications = runtime.block_on(async {
+            self.db.replace_applications(&applications).await?;
+            self.db.applications().await
+        })?;
+        Ok(())
+    }
+
+    fn update_config(&mut self, config: &Config) -> plugin_api::Result<()> {
+        self.aliases = Self::aliases(config);
+        Ok(())
+    }
+
+    fn search(&mut self, query: &plugin_api::Query) -> Vec<plugin_api::SearchResult> {
+        let text = query.text.trim();
+        if text.is_empty() {
+            return vec![];
+        }
+        let mut results: Vec<(&Application, f32, Vec<Range<usize>>)> = self
+            .applications
+            .iter()
+            .filter_map(|application| {
+                let (score, ranges) = self.match_application(text, application)?;
+                let launches = application.launch_count as f32;
+                let boost = LAUNCH_WEIGHT * launches / (launches + LAUNCHES_TO_HALF);
+                Some((application, (score + boost).min(1.0), ranges))
+            })
+            .collect();
+        results.sort_by(|(a, a_score, _), (b, b_score, _)| {
+            b_score.total_cmp(a_score).then_with(|| a.name.cmp(&b.name))
+        });
+
+        results
+            .into_iter()
+            .take(RESULT_LIMIT)
+            .map(|(application, score, ranges)| {
+                plugin_api::SearchResult::new(
+                    application.name.clone(),
+                    Some(
+                        application
+                            .generic_name
+                            .clone()
+                            .unwrap_or_else(|| application.path.clone()),
+                    ),
+                    None,
+                    Some(plugin_api::Action::LaunchApplication(
+                        application.target().to_string(),
+                    )),
+                    Some(40),
+                )
+                .with_score(score)
+                .with_matches(ranges, vec![])
+                .with_actions(vec![plugin_api::ResultAction::new(
+                    "Show in file manager",
+                    plugin_api::Action::RevealInFileManager(application.path.clone()),
+                )
+                .with_shortcut("Ctrl+Enter")])
+            })
+            .collect()
+    }
+
+    fn record_usage(&mut self, result: &plugin_api::SearchResult) -> plugin_api::Result<()> {
+        let Some(plugin_api::Action::LaunchApplication(target)) = result.action() else {
+            return Ok(());
+        };
+        let runtime = self.runtime()?;
+        for application in &mut self.applications {
+            if application.target() == target {
+                runtime.block_on(self.db.record_launch(&application.id))?;
+                application.launch_count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_metadata(&self) -> plugin_api::Metadata {
+        plugin_api::Metadata {
+            name: "Applications".to_string(),
+            description: "Launch installed applications".to_string(),
+            icon: None,
+            url: None,
+        }
+    }
+
+    fn destroy(&mut self) {
+        println!("Applications plugin destroyed!");
+    }
+
+    fn clone_box(&self) -> Box<dyn Plugin> {
+        Box::new(self.clone())
+    }
+
+    fn get_config_schema(&self) -> plugin_api::config::ConfigSchema {
+        vec![ConfigField::new(
+            "aliases",
+            ConfigKind::TableList {
+                fields: vec![
+                    ConfigField::new(
+                        "alias",
+                        ConfigKind::String,
+                        ConfigValue::String(String::new()),
+                        "What to type, e.g. `ff`",
+                    ),
+                    ConfigField::new(
+                        "application",
+                        ConfigKind::String,
+                        ConfigValue::String(String::new()),
+                        "Name of the application, or of its `.desktop` entry or `.app` bundle",
+                    ),
+                ],
+            },
+            ConfigValue::Tables(vec![]),
+            "Shortcuts finding an application",
+        )]
+    }
+}
+
+impl ApplicationsPlugin {
+    fn aliases(config: &Config) -> Vec<Alias> {
+        config
+            .get("aliases")
+            .and_then(ConfigValue::as_tables)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|table| {
+                let field = |key: &str| {
+                    table
+                        .get(key)
+                        .and_then(ConfigValue::as_str)
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(|value| value.to_string())
+                };
+                Some(Alias {
+                    alias: field("alias")?,
+                    application: field("application")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Applications match by name, a configured alias, or otherwise by their generic name and
+    /// keywords, e.g. `browser` for Firefox. Only name matches are highlighted.
+    fn match_application(
+        &self,
+        query: &str,
+        application: &Application,
+    ) -> Option<(f32, Vec<Range<usize>>)> {
+        let by_name = fuzzy::fuzzy_match(query, &application.name)
+            .map(|matched| (matched.normalized_score(), matched.ranges));
+        // aliases are typed out, only exact and prefix matches count
+        let by_alias = self
+            .aliases
+            .iter()
+            .filter(|alias| Self::is_named(application, &alias.application))
+            .map(|alias| ranking::match_quality(query, &alias.alias))
+            .filter(|quality| *quality >= 0.8)
+            .max_by(f32::total_cmp)
+            .map(|quality| (quality, vec![]));
+        let by_keywords = || {
+            // joined like path segments, so matches at the start of each keyword score higher
+            let keywords: Vec<&str> = application
+                .generic_name
+                .iter()
+                .chain(&application.keywords)
+                .map(String::as_str)
+                .collect();
+            fuzzy::fuzzy_match(query, &keywords.join("/"))
+                .map(|matched| (KEYWORD_WEIGHT * matched.normalized_score(), vec![]))
+        };
+        match (by_name, by_alias) {
+            (Some(by_name), Some(by_alias)) if by_alias.0 > by_name.0 => Some(by_alias),
+            (Some(by_name), _) => Some(by_name),
+            (None, Some(by_alias)) => Some(by_alias),
+            (None, None) => by_keywords(),
+        }
+    }
+
+    /// Whether `name` is the name of the application, or of its `.desktop` entry or bundle
+    fn is_named(application: &Application, name: &str) -> bool {
+        application.name.eq_ignore_ascii_case(name)
+            || Path::new(&application.path)
+                .file_stem()
+                .is_some_and(|stem| stem.to_string_lossy().eq_ignore_ascii_case(name))
+    }
+
+    fn runtime(&self) -> plugin_api::Result<tokio::runtime::Handle> {
+        self.runtime
+            .clone()
+            .ok_or_else(|| plugin_api::Error::other("Applications plugin is not initialised"))
+    }
+}
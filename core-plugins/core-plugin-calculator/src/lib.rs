@@ -1,70 +1,43 @@
 mod token;
 
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
 use plugin_api::Plugin;
 use token::Token;
 
-#[derive(Clone)]
-pub struct CalculatorPlugin {
-    result: Option<f64>,
-    client_state: Arc<Mutex<plugin_api::ClientState>>,
-}
-
-impl Default for CalculatorPlugin {
-    fn default() -> Self {
-        Self {
-            result: None,
-            client_state: Arc::new(Mutex::new(plugin_api::ClientState::default())),
-        }
-    }
-}
+#[derive(Clone, Default)]
+pub struct CalculatorPlugin {}
 
 impl Plugin for CalculatorPlugin {
     fn init(
         &mut self,
-        client_state_arc: Arc<Mutex<plugin_api::ClientState>>,
         _loaded_plugin: Arc<Mutex<plugin_api::LoadedPlugin>>,
-    ) {
-        self.client_state = client_state_arc;
+    ) -> plugin_api::Result<()> {
         println!("Calculator plugin initialized!");
+        Ok(())
     }
 
-    fn start(&mut self) {
-        let mut client_state = self
-            .client_state
-            .lock()
-            .expect("Failed to lock client state");
+    fn start(&mut self) -> plugin_api::Result<()> {
+        Ok(())
+    }
 
-        let query = client_state.get_search_query();
-        let result = CalculatorPlugin::calculate(query);
-        if let Ok(result) = result {
-            if let Some(existing_result) = self.result {
-                if existing_result == result {
-                    return;
-                }
-            }
-            self.result = Some(result);
-        } else {
-            self.result = None;
-        }
-        if let Some(result) = self.result {
-            let existing_results = client_state.get_search_results();
-            let mut new_results = Vec::new();
-            for result in existing_results {
-                new_results.push(result);
-            }
-            new_results.push(plugin_api::SearchResult::new(
+    fn search(&mut self, query: &plugin_api::Query) -> Vec<plugin_api::SearchResult> {
+        match CalculatorPlugin::calculate(&query.text) {
+            Ok(result) => vec![plugin_api::SearchResult::new(
                 format!("= {result}"),
                 None,
                 Some(plugin_api::Icon::Copy),
-                Some(plugin_api::Action::Copy),
+                Some(plugin_api::Action::CopyText(result.to_string())),
                 Some(10),
-            ));
-            client_state.update_search_results(new_results);
+            )
+            // an expression that evaluates is exactly what the user asked for
+            .with_score(1.0)
+            .with_actions(vec![plugin_api::ResultAction::new(
+                "Type result",
+                plugin_api::Action::TypeText(result.to_string()),
+            )
+            .with_shortcut("Ctrl+Enter")])],
+            Err(_) => vec![],
         }
     }
     fn get_metadata(&self) -> plugin_api::Metadata {
@@ -82,8 +55,8 @@ impl Plugin for CalculatorPlugin {
         Box::new(self.clone())
     }
 
-    fn get_config(&self) -> HashMap<String, String> {
-        HashMap::new()
+    fn get_config_schema(&self) -> plugin_api::config::ConfigSchema {
+        vec![]
     }
 }
 
@@ -0,0 +1,68 @@
+//! Query latency of the FS Walk plugin against an index of generated files
+use core_plugin_fs::FsPlugin;
+use criterion::{criterion_group, criterion_main, Criterion};
+use plugin_api::{config::ConfigValue, LoadedPlugin, Plugin, Query};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+const DIRECTORIES: usize = 20;
+const FILES_PER_DIRECTORY: usize = 50;
+
+fn search(c: &mut Criterion) {
+    let home = tempfile::tempdir().expect("Failed to create temporary home directory");
+    for directory in 0..DIRECTORIES {
+        let directory = home.path().join(format!("directory_{directory}"));
+        std::fs::create_dir(&directory).expect("Failed to create directory");
+        for file in 0..FILES_PER_DIRECTORY {
+            std::fs::File::create(directory.join(format!("file_{file}.txt")))
+                .expect("Failed to create file");
+        }
+    }
+    // keeps the cache database and the initial index inside the temporary directory
+    std::env::set_var("HOME", home.path());
+    std::env::remove_var("DATABASE_URL");
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+    let mut plugin = FsPlugin::default();
+    let loaded_plugin = LoadedPlugin {
+        plugin: plugin.clone_box(),
+        metadata: plugin.get_metadata(),
+        config: HashMap::from([(
+            "roots".to_string(),
+            ConfigValue::Tables(vec![HashMap::from([(
+                "path".to_string(),
+                ConfigValue::String(home.path().to_string_lossy().to_string()),
+            )])]),
+        )]),
+        schema: plugin.get_config_schema(),
+        enabled: true,
+        status: Default::default(),
+        crashes: 0,
+        runtime: runtime.handle().clone(),
+    };
+    plugin
+        .init(Arc::new(Mutex::new(loaded_plugin)))
+        .expect("Failed to initialise plugin");
+    plugin.start().expect("Failed to index files");
+    plugin.wait_until_indexed();
+
+    let mut group = c.benchmark_group("fs");
+    for text in ["file_7", "directory_13/file_42", "missing"] {
+        let query = Query {
+            id: 0,
+            text: text.to_string(),
+        };
+        if text != "missing" {
+            assert!(!plugin.search(&query).is_empty(), "{text} isn't indexed");
+        }
+        group.bench_function(format!("search {text}"), |b| {
+            b.iter(|| plugin.search(&query))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+-- trigram tokens match any substring of at least 3 characters, case-insensitively
+CREATE VIRTUAL TABLE IF NOT EXISTS filesystem_fts USING fts5(
+    name,
+    path,
+    content = 'filesystem',
+    content_rowid = 'id',
+    tokenize = 'trigram'
+);
+
+CREATE TRIGGER IF NOT EXISTS filesystem_fts_insert AFTER INSERT ON filesystem BEGIN
+    INSERT INTO filesystem_fts (rowid, name, path) VALUES (new.id, new.name, new.path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS filesystem_fts_delete AFTER DELETE ON filesystem BEGIN
+    INSERT INTO filesystem_fts (filesystem_fts, rowid, name, path) VALUES ('delete', old.id, old.name, old.path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS filesystem_fts_update AFTER UPDATE OF name, path ON filesystem BEGIN
+    INSERT INTO filesystem_fts (filesystem_fts, rowid, name, path) VALUES ('delete', old.id, old.name, old.path);
+    INSERT INTO filesystem_fts (rowid, name, path) VALUES (new.id, new.name, new.path);
+END;
+
+-- index the entries cached before this migration
+INSERT INTO filesystem_fts (filesystem_fts) VALUES ('rebuild');
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_parent;
+ALTER TABLE filesystem DROP COLUMN parent;
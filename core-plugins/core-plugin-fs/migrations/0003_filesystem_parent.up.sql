@@ -0,0 +1,10 @@
+ALTER TABLE filesystem ADD COLUMN parent TEXT NOT NULL DEFAULT '';
+
+-- everything before the last `/`, rtrim strips the trailing characters that aren't slashes
+UPDATE filesystem SET parent = substr(
+    rtrim(path, replace(path, '/', '')),
+    1,
+    length(rtrim(path, replace(path, '/', ''))) - 1
+);
+
+CREATE INDEX IF NOT EXISTS idx_parent ON filesystem(parent);
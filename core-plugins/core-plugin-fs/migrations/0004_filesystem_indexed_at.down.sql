@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_indexed_at;
+ALTER TABLE filesystem DROP COLUMN indexed_at;
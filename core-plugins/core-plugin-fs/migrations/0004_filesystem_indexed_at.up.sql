@@ -0,0 +1,4 @@
+-- unix time in milliseconds of the last write, rows a full scan didn't write are stale
+ALTER TABLE filesystem ADD COLUMN indexed_at INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_indexed_at ON filesystem(indexed_at);
@@ -0,0 +1 @@
+-- nothing to restore, the Applications plugin keeps indexing applications
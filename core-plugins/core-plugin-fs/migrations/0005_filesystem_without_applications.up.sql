@@ -0,0 +1,2 @@
+-- applications are indexed by the Applications plugin
+DELETE FROM filesystem WHERE kind = 'application';
@@ -0,0 +1,2 @@
+-- lets scans that can't use the trigram index visit short paths first and stop at the limit
+CREATE INDEX IF NOT EXISTS idx_path_length ON filesystem(length(path));
@@ -1,5 +1,18 @@
-use crate::db;
+use crate::db::{
+    self,
+    fs::{Entry, EntryKind, Fs, Root},
+};
 use sqlx::Row;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// an upsert rather than INSERT OR REPLACE, whose implicit delete skips the triggers keeping the
+// full text index in sync
+const UPSERT_ENTRY: &str = "INSERT INTO filesystem (name, path, parent, kind, ctime, mtime, atime, indexed_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT(path) DO UPDATE SET name = excluded.name, parent = excluded.parent, kind = excluded.kind, ctime = excluded.ctime, mtime = excluded.mtime, atime = excluded.atime, indexed_at = excluded.indexed_at, modified_at = CURRENT_TIMESTAMP";
 
 #[derive(Debug, Clone)]
 pub enum CacheType {
@@ -22,7 +35,7 @@ pub struct CacheEntry {
 #[derive(Debug, Clone)]
 pub struct Cache {
     pub filesystem: CacheEntry,
-    pub filesystem_root: Option<String>,
+    pub roots: Vec<Root>,
 }
 
 impl Default for Cache {
@@ -32,15 +45,15 @@ impl Default for Cache {
                 r#type: CacheType::Filesystem,
                 status: CacheStatus::Outdated,
             },
-            filesystem_root: None,
+            roots: vec![],
         }
     }
 }
 
 impl Cache {
-    pub async fn init(&mut self, db: &db::Db) {
+    pub async fn init(&mut self, db: &db::Db, cancelled: &AtomicBool) -> plugin_api::Result<()> {
         self.update_cache_states(db).await;
-        self.cache_file_system(db, false, false).await;
+        self.cache_file_system(db, false, cancelled).await
     }
     async fn update_cache_states(&mut self, db: &db::Db) {
         let pool = match db.pool.as_ref() {
@@ -67,53 +80,468 @@ impl Cache {
             false
         }
     }
-    pub async fn cache_file_system(&mut self, db: &db::Db, upsert: bool, overwrite: bool) {
-        if !(upsert || (!upsert && !self.get_cache_status())) {
-            return;
-        }
-        if overwrite {
-            dbg!("Overwriting file system cache");
-            self.filesystem.status = CacheStatus::Outdated;
-            let pool = db.pool.as_ref().unwrap();
-            let _ = sqlx::query("DELETE FROM filesystem").execute(pool).await;
+    /// Indexes all roots, then sweeps the entries the scan didn't see since they no longer
+    /// exist. Skipped if the cache is populated already, unless `upsert` is set. A scan that's
+    /// `cancelled` leaves the cache as it was.
+    pub async fn cache_file_system(
+        &mut self,
+        db: &db::Db,
+        upsert: bool,
+        cancelled: &AtomicBool,
+    ) -> plugin_api::Result<()> {
+        if !upsert && self.get_cache_status() {
+            return Ok(());
         }
-
+        let pool = db.pool()?;
         self.filesystem.status = CacheStatus::Updating;
         dbg!("Caching file system");
-        let entries;
-        if let Some(root) = &self.filesystem_root {
-            println!("Indexing with root as {}", root);
-            entries = db::fs::Fs::index_file_system(Some(root));
-        } else {
-            println!("Indexing with default root");
-            entries = db::fs::Fs::index_file_system(None);
+        let scan_started = now_millis();
+        for root in &self.roots {
+            println!("Indexing with root as {}", root.path);
         }
-        let pool = db.pool.as_ref().unwrap();
-        let query = "INSERT OR REPLACE INTO filesystem (name, path, kind, ctime, mtime, atime) VALUES ($1, $2, $3, $4, $5, $6)";
+        let Some(entries) = Fs::index_file_system(&self.roots, cancelled) else {
+            self.filesystem.status = CacheStatus::Outdated;
+            return Ok(());
+        };
         dbg!("Creating transactions for file system index");
-        let mut tx = pool.begin().await.unwrap();
-        for db::fs::Entry {
-            name,
-            path,
-            kind,
-            ctime,
-            mtime,
-            atime,
-        } in entries
-        {
-            let _ = sqlx::query(query)
-                .bind(&name)
-                .bind(&path)
-                .bind(kind.as_str())
-                .bind(&ctime)
-                .bind(&mtime)
-                .bind(&atime)
-                .execute(&mut *tx)
-                .await;
-        }
-        dbg!("Committing transactions for file system index");
-        tx.commit().await.unwrap();
+        upsert_entries(pool, &entries).await?;
+        // the sweep is what makes the cache match this scan's roots, another scan may follow
+        if cancelled.load(Ordering::Relaxed) {
+            self.filesystem.status = CacheStatus::Outdated;
+            return Ok(());
+        }
+        // entries written by the watcher during the scan are newer and survive too
+        let swept = sqlx::query("DELETE FROM filesystem WHERE indexed_at < $1")
+            .bind(scan_started)
+            .execute(pool)
+            .await
+            .map_err(plugin_api::Error::other)?
+            .rows_affected();
+        println!("Removed {} stale entries from the file system cache", swept);
         self.filesystem.status = CacheStatus::Updated;
         dbg!("Completed caching file system");
+        Ok(())
+    }
+
+    /// Points the cache at new roots, dropping the entries outside of them and reconciling the
+    /// rest instead of indexing everything from scratch
+    pub async fn change_roots(
+        &mut self,
+        db: &db::Db,
+        roots: Vec<Root>,
+        cancelled: &AtomicBool,
+    ) -> plugin_api::Result<()> {
+        let pool = db.pool()?;
+        self.roots = roots;
+        self.filesystem.status = CacheStatus::Updating;
+        let mut statement = String::from("DELETE FROM filesystem WHERE TRUE");
+        let mut query_ranges = vec![];
+        for root in &self.roots {
+            statement.push_str(" AND NOT (path >= ? AND path < ?)");
+            query_ranges.push(subtree_range(&root.path));
+        }
+        let mut query = sqlx::query(&statement);
+        for (start, end) in query_ranges {
+            query = query.bind(start).bind(end);
+        }
+        query
+            .execute(pool)
+            .await
+            .map_err(plugin_api::Error::other)?;
+        for root in &self.roots {
+            reconcile(pool, &self.roots, root, cancelled).await?;
+        }
+        self.filesystem.status = CacheStatus::Updated;
+        Ok(())
+    }
+}
+
+/// Bounds of the paths below `path`, as a range the path index can be used for. `0` is the
+/// character following `/`.
+fn subtree_range(path: &str) -> (String, String) {
+    let path = path.trim_end_matches('/');
+    (format!("{path}/"), format!("{path}0"))
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn is_within(path: &str, directory: &str) -> bool {
+    path.strip_prefix(directory)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+pub async fn upsert_entries(pool: &sqlx::SqlitePool, entries: &[Entry]) -> plugin_api::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let indexed_at = now_millis();
+    let mut tx = pool.begin().await.map_err(plugin_api::Error::other)?;
+    for entry in entries {
+        sqlx::query(UPSERT_ENTRY)
+            .bind(&entry.name)
+            .bind(&entry.path)
+            .bind(entry.parent())
+            .bind(entry.kind.as_str())
+            .bind(entry.ctime)
+            .bind(entry.mtime)
+            .bind(entry.atime)
+            .bind(indexed_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(plugin_api::Error::other)?;
+    }
+    tx.commit().await.map_err(plugin_api::Error::other)
+}
+
+/// Removes the entry at `path` and, if it was a directory, everything below it
+pub async fn remove_subtree(pool: &sqlx::SqlitePool, path: &str) -> plugin_api::Result<()> {
+    let (start, end) = subtree_range(path);
+    sqlx::query("DELETE FROM filesystem WHERE path = $1 OR (path >= $2 AND path < $3)")
+        .bind(path)
+        .bind(start)
+        .bind(end)
+        .execute(pool)
+        .await
+        .map_err(plugin_api::Error::other)?;
+    Ok(())
+}
+
+/// Brings the given paths up to date with the filesystem, e.g. after watcher events. Entries
+/// below several roots are indexed if any of them includes them. Returns the directories that
+/// appeared, to be watched too.
+pub async fn sync_paths(
+    pool: &sqlx::SqlitePool,
+    roots: &[Root],
+    paths: &BTreeSet<PathBuf>,
+) -> plugin_api::Result<Vec<PathBuf>> {
+    let mut entries = vec![];
+    let mut directories = vec![];
+    for path in paths {
+        let included = roots
+            .iter()
+            .find_map(|root| root.entry(path).map(|(entry, depth)| (root, entry, depth)));
+        match included {
+            Some((root, entry, depth)) => {
+                let known = sqlx::query("SELECT EXISTS (SELECT 1 FROM filesystem WHERE path = $1)")
+                    .bind(&entry.path)
+                    .fetch_one(pool)
+                    .await
+                    .map(|row| row.get::<bool, _>(0))
+                    .map_err(plugin_api::Error::other)?;
+                // a directory appearing at once, e.g. moved in, brings its contents along
+                if entry.kind == EntryKind::Directory && !known {
+                    entries.extend(root.index_directory(path, depth));
+                    directories.extend(root.directories_to_watch(path, depth));
+                }
+                entries.push(entry);
+            }
+            // excluded paths that still exist, e.g. inside excluded directories, are skipped
+            None if std::fs::symlink_metadata(path).is_ok() => {}
+            None => remove_subtree(pool, &path.to_string_lossy()).await?,
+        }
+    }
+    upsert_entries(pool, &entries).await?;
+    Ok(directories)
+}
+
+/// Catches up with changes the watcher missed. A directory's mtime changes whenever entries are
+/// added to, removed from or renamed in it, so only directories whose mtime differs from the
+/// cached one are listed again. Returns the directories that appeared, to be watched too, and
+/// stops listing directories once `cancelled` is set.
+pub async fn reconcile(
+    pool: &sqlx::SqlitePool,
+    roots: &[Root],
+    root: &Root,
+    cancelled: &AtomicBool,
+) -> plugin_api::Result<Vec<PathBuf>> {
+    let (start, end) = subtree_range(&root.path);
+    let directories = sqlx::query(
+        "SELECT path, mtime FROM filesystem WHERE kind = 'directory' AND path >= $1 AND path < $2 ORDER BY path",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await
+    .map_err(plugin_api::Error::other)?;
+
+    // the root itself isn't cached, its listing is always compared
+    let mut changed = vec![root.path.clone()];
+    let mut removed: Vec<String> = vec![];
+    for record in directories {
+        let path: String = record.get("path");
+        let mtime: i64 = record.get("mtime");
+        if removed.iter().any(|directory| is_within(&path, directory)) {
+            continue;
+        }
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => {
+                if metadata.mtime() != mtime {
+                    changed.push(path);
+                }
+            }
+            _ => {
+                remove_subtree(pool, &path).await?;
+                removed.push(path);
+            }
+        }
+    }
+    let mut appeared = vec![];
+    for directory in changed {
+        if cancelled.load(Ordering::Relaxed) {
+            break;
+        }
+        appeared.extend(relist(pool, roots, root, &directory).await?);
+    }
+    Ok(appeared)
+}
+
+/// Compares the cached children of a directory with the ones on disk, returning the directories
+/// that appeared
+async fn relist(
+    pool: &sqlx::SqlitePool,
+    roots: &[Root],
+    root: &Root,
+    directory: &str,
+) -> plugin_api::Result<Vec<PathBuf>> {
+    let path = Path::new(directory);
+    let Some(depth) = root.depth_of(path, true) else {
+        return Ok(vec![]);
+    };
+    let on_disk = if depth < root.depth {
+        root.list_directory(path)
+    } else {
+        vec![]
+    };
+    let stored: HashMap<String, i64> =
+        sqlx::query("SELECT path, mtime FROM filesystem WHERE parent = $1")
+            .bind(directory)
+            .fetch_all(pool)
+            .await
+            .map_err(plugin_api::Error::other)?
+            .iter()
+            .map(|record| (record.get("path"), record.get("mtime")))
+            .collect();
+
+    let on_disk_paths: HashSet<String> = on_disk.iter().map(|entry| entry.path.clone()).collect();
+    for stored_path in stored.keys() {
+        // another root may still include the entry with its own rules
+        if !on_disk_paths.contains(stored_path)
+            && !roots
+                .iter()
+                .any(|root| root.entry(Path::new(stored_path)).is_some())
+        {
+            remove_subtree(pool, stored_path).await?;
+        }
+    }
+
+    let mut entries = vec![];
+    let mut directories = vec![];
+    // refreshes the directory's own mtime
+    entries.extend(root.entry(path).map(|(entry, _)| entry));
+    for entry in on_disk {
+        match stored.get(&entry.path) {
+            Some(mtime) if *mtime == entry.mtime => {}
+            Some(_) => entries.push(entry),
+            None => {
+                if entry.kind == EntryKind::Directory {
+                    let path = Path::new(&entry.path);
+                    entries.extend(root.index_directory(path, depth + 1));
+                    directories.extend(root.directories_to_watch(path, depth + 1));
+                }
+                entries.push(entry);
+            }
+        }
+    }
+    upsert_entries(pool, &entries).await?;
+    Ok(directories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_api::config::ConfigValue;
+    use std::time::{Duration, SystemTime};
+
+    /// A cache of the `tree` directory, kept apart from the database next to it
+    struct Fixture {
+        directory: tempfile::TempDir,
+        runtime: tokio::runtime::Runtime,
+        db: db::Db,
+        cache: Cache,
+    }
+
+    impl Fixture {
+        fn new(files: &[&str]) -> Self {
+            let directory = tempfile::tempdir().expect("Failed to create temporary directory");
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+            let mut db = db::Db::default();
+            let database = directory.path().join("cache.sqlite");
+            db.init(
+                Some(database.to_string_lossy().to_string()),
+                runtime.handle(),
+            )
+            .expect("Failed to open the cache database");
+            let tree = directory.path().join("tree");
+            let root = Root::from_config(
+                &HashMap::from([(
+                    "path".to_string(),
+                    ConfigValue::String(tree.to_string_lossy().to_string()),
+                )]),
+                &[],
+            )
+            .expect("Failed to read the root");
+            let fixture = Self {
+                directory,
+                runtime,
+                db,
+                cache: Cache {
+                    roots: vec![root],
+                    ..Default::default()
+                },
+            };
+            fixture.create(files);
+            fixture
+        }
+
+        fn path(&self, path: &str) -> PathBuf {
+            self.directory.path().join("tree").join(path)
+        }
+
+        /// Creates files and, for paths ending with `/`, directories
+        fn create(&self, paths: &[&str]) {
+            for path in paths {
+                let path = self.path(path);
+                std::fs::create_dir_all(if path.to_string_lossy().ends_with('/') {
+                    &path
+                } else {
+                    path.parent().expect("Paths lie below the tree")
+                })
+                .expect("Failed to create directory");
+                if !path.to_string_lossy().ends_with('/') {
+                    std::fs::File::create(&path).expect("Failed to create file");
+                }
+            }
+        }
+
+        /// Moves the mtime of a directory into the past, mtimes only change by the second
+        fn age(&self, directory: &str) {
+            std::fs::File::open(self.path(directory))
+                .and_then(|file| file.set_modified(SystemTime::now() - Duration::from_secs(60)))
+                .expect("Failed to change the mtime");
+        }
+
+        fn scan(&mut self, cancelled: bool) {
+            // entries the scan didn't write are told apart by the millisecond they were written
+            std::thread::sleep(Duration::from_millis(2));
+            self.runtime
+                .block_on(
+                    self.cache
+                        .cache_file_system(&self.db, true, &AtomicBool::new(cancelled)),
+                )
+                .expect("Failed to scan the tree");
+        }
+
+        fn reconcile(&self) -> Vec<PathBuf> {
+            let roots = &self.cache.roots;
+            self.runtime
+                .block_on(reconcile(
+                    self.db.pool().expect("The database is connected"),
+                    roots,
+                    &roots[0],
+                    &AtomicBool::new(false),
+                ))
+                .expect("Failed to reconcile the tree")
+        }
+
+        /// The cached paths relative to the tree
+        fn cached(&self) -> Vec<String> {
+            let tree = self.path("");
+            self.runtime
+                .block_on(
+                    sqlx::query("SELECT path FROM filesystem ORDER BY path")
+                        .fetch_all(self.db.pool().expect("The database is connected")),
+                )
+                .expect("Failed to read the cache")
+                .iter()
+                .map(|record| {
+                    Path::new(record.get::<&str, _>("path"))
+                        .strip_prefix(&tree)
+                        .expect("Only the tree is cached")
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn rescan_sweeps_deleted_entries_and_keeps_the_rest() {
+        let mut fixture = Fixture::new(&["a/x.txt", "a/y.txt", "b/z.txt"]);
+        fixture.scan(false);
+        assert_eq!(
+            fixture.cached(),
+            ["a", "a/x.txt", "a/y.txt", "b", "b/z.txt"]
+        );
+
+        std::fs::remove_file(fixture.path("a/y.txt")).unwrap();
+        std::fs::remove_dir_all(fixture.path("b")).unwrap();
+        fixture.create(&["c.txt"]);
+        fixture.scan(false);
+        assert_eq!(fixture.cached(), ["a", "a/x.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn cancelled_scan_leaves_the_cache_alone() {
+        let mut fixture = Fixture::new(&["a/x.txt", "a/y.txt"]);
+        fixture.scan(false);
+
+        std::fs::remove_file(fixture.path("a/y.txt")).unwrap();
+        fixture.create(&["a/z.txt"]);
+        fixture.scan(true);
+        assert_eq!(fixture.cached(), ["a", "a/x.txt", "a/y.txt"]);
+    }
+
+    #[test]
+    fn reconcile_relists_directories_whose_mtime_changed() {
+        let mut fixture = Fixture::new(&["a/x.txt", "a/y.txt", "b/z.txt"]);
+        fixture.age("a");
+        fixture.age("b");
+        fixture.scan(false);
+
+        std::fs::remove_file(fixture.path("a/y.txt")).unwrap();
+        fixture.create(&["a/new.txt", "c/w.txt"]);
+        assert_eq!(fixture.reconcile(), [fixture.path("c")]);
+        assert_eq!(
+            fixture.cached(),
+            ["a", "a/new.txt", "a/x.txt", "b", "b/z.txt", "c", "c/w.txt"]
+        );
+    }
+
+    #[test]
+    fn reconcile_skips_directories_whose_mtime_is_unchanged() {
+        let mut fixture = Fixture::new(&["a/x.txt"]);
+        fixture.age("a");
+        fixture.scan(false);
+
+        // as if the change happened within the second the directory was cached in
+        fixture.create(&["a/unnoticed.txt"]);
+        fixture.age("a");
+        fixture.reconcile();
+        assert_eq!(fixture.cached(), ["a", "a/x.txt"]);
+    }
+
+    #[test]
+    fn reconcile_removes_directories_that_disappeared() {
+        let mut fixture = Fixture::new(&["a/b/x.txt", "c.txt"]);
+        fixture.scan(false);
+
+        std::fs::remove_dir_all(fixture.path("a")).unwrap();
+        fixture.reconcile();
+        assert_eq!(fixture.cached(), ["c.txt"]);
     }
 }
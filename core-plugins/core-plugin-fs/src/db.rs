@@ -19,27 +19,33 @@ impl Default for Db {
 }
 
 impl Db {
-    pub fn init(&mut self, database_url: Option<String>) {
+    pub fn init(
+        &mut self,
+        database_url: Option<String>,
+        runtime: &tokio::runtime::Handle,
+    ) -> plugin_api::Result<()> {
         dotenvy::dotenv().ok();
-        self.connection_url = Self::get_database_url(database_url);
-        Self::create_db_files(&self.connection_url.as_ref().unwrap());
+        let connection_url = Self::get_database_url(database_url).ok_or_else(|| {
+            plugin_api::Error::other("Unable to determine the cache database path")
+        })?;
+        Self::create_db_files(&connection_url)?;
+        self.connection_url = Some(connection_url.clone());
 
-        tokio::runtime::Runtime::new()
-            .expect("Failed to create Tokio runtime")
-            .block_on(async {
-                dbg!("Connecting to database: {:?}", &self.connection_url);
-                let pool = sqlx::SqlitePool::connect(&self.connection_url.as_ref().unwrap())
-                    .await
-                    .unwrap();
-                self.pool = Some(pool);
+        runtime.block_on(async {
+            dbg!("Connecting to database: {:?}", &connection_url);
+            let pool = sqlx::SqlitePool::connect(&connection_url)
+                .await
+                .map_err(plugin_api::Error::other)?;
+            Self::run_migrations(&pool).await?;
+            self.pool = Some(pool);
+            Ok(())
+        })
+    }
 
-                Self::run_migrations(
-                    self.pool
-                        .as_ref()
-                        .expect("SQLite connection pool should be Some"),
-                )
-                .await;
-            });
+    pub fn pool(&self) -> plugin_api::Result<&sqlx::SqlitePool> {
+        self.pool
+            .as_ref()
+            .ok_or_else(|| plugin_api::Error::other("Cache database is not connected"))
     }
 
     fn get_database_url(database_url: Option<String>) -> Option<String> {
@@ -63,24 +69,24 @@ impl Db {
             }
         }
     }
-    fn create_db_files(database_url: &str) {
-        if !std::path::Path::new(database_url).exists() {
-            let path = std::path::Path::new(database_url);
-            let prefix = path.parent().unwrap();
-            std::fs::create_dir_all(prefix).unwrap();
-            if let Ok(_file) = std::fs::File::create_new(path) {
-                dbg!("created file");
-            } else {
-                panic!("Unable to create file");
+    fn create_db_files(database_url: &str) -> std::io::Result<()> {
+        let path = std::path::Path::new(database_url);
+        if !path.exists() {
+            if let Some(prefix) = path.parent() {
+                std::fs::create_dir_all(prefix)?;
             }
+            std::fs::File::create_new(path)?;
+            dbg!("created file");
         }
+        Ok(())
     }
 
-    async fn run_migrations(pool: &sqlx::SqlitePool) {
-        if let Ok(()) = sqlx::migrate!().run(pool).await {
-            dbg!("Migrations completed");
-        } else {
-            dbg!("Migrations failed");
-        }
+    async fn run_migrations(pool: &sqlx::SqlitePool) -> plugin_api::Result<()> {
+        sqlx::migrate!()
+            .run(pool)
+            .await
+            .map_err(|error| plugin_api::Error::other(format!("Migrations failed: {}", error)))?;
+        dbg!("Migrations completed");
+        Ok(())
     }
 }
@@ -1,22 +1,35 @@
-use directories::BaseDirs;
-use std::os::unix::fs::MetadataExt;
-use walkdir::WalkDir;
+use ignore::{
+    gitignore::Gitignore,
+    overrides::{Override, OverrideBuilder},
+    Match, WalkBuilder,
+};
+use plugin_api::config::{self, Config, ConfigField, ConfigValue};
+use std::{
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// How deep below a root directory entries are indexed, unless it's configured otherwise
+pub const DEFAULT_DEPTH: usize = 5;
+/// Files listing entries to skip, in order of precedence
+const IGNORE_FILES: [&str; 3] = [".finignore", ".ignore", ".gitignore"];
 #[derive(serde::Serialize, Clone, PartialEq)]
 pub enum EntryKind {
     File,
     Directory,
     Symlink,
-    Application,
 }
 
-impl From<&str> for EntryKind {
-    fn from(value: &str) -> Self {
+impl TryFrom<&str> for EntryKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
-            "file" => Self::File,
-            "directory" => Self::Directory,
-            "symlink" => Self::Symlink,
-            "application" => Self::Application,
-            _ => panic!("Failed to parse file kind!"),
+            "file" => Ok(Self::File),
+            "directory" => Ok(Self::Directory),
+            "symlink" => Ok(Self::Symlink),
+            _ => Err(format!("Unknown file kind `{}`", value)),
         }
     }
 }
@@ -27,7 +40,6 @@ impl EntryKind {
             Self::File => "file",
             Self::Directory => "directory",
             Self::Symlink => "symlink",
-            Self::Application => "application",
         }
     }
 }
@@ -41,83 +53,510 @@ pub struct Entry {
     pub mtime: i64,
     pub atime: i64,
 }
-pub struct Fs {}
 
-impl Fs {
-    pub fn index_file_system(root: Option<&str>) -> Vec<Entry> {
-        let walk;
-        if let Some(root) = root {
-            walk = WalkDir::new(root);
+impl Entry {
+    /// Reads the entry at `path`, symlinks are read as the entry they point to if followed
+    pub fn from_path(path: &Path, follow_symlinks: bool) -> Option<Self> {
+        let metadata = if follow_symlinks {
+            std::fs::metadata(path)
         } else {
-            walk = WalkDir::new(BaseDirs::new().unwrap().home_dir());
+            std::fs::symlink_metadata(path)
         }
-        let mut entries = Vec::new();
+        .ok()?;
+        let kind = if metadata.is_file() {
+            EntryKind::File
+        } else if metadata.is_dir() {
+            EntryKind::Directory
+        } else {
+            EntryKind::Symlink
+        };
+        Some(Self {
+            name: path.file_name()?.to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            kind,
+            ctime: metadata.ctime(),
+            mtime: metadata.mtime(),
+            atime: metadata.atime(),
+        })
+    }
 
-        // index files
-        for entry in walk
-            .min_depth(1)
-            .max_depth(5)
-            .follow_links(true)
-            .into_iter()
-            .filter_entry(|entry| {
-                entry
-                    .file_name()
-                    .to_str()
-                    .map(|file_name| !file_name.starts_with("."))
-                    .unwrap_or(false)
-            })
+    /// The path of the directory containing the entry, stored to look up directory listings
+    pub fn parent(&self) -> &str {
+        parent(&self.path)
+    }
+}
+
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+fn is_hidden(file_name: &str) -> bool {
+    file_name.starts_with(".")
+}
+
+/// A directory to index, along with the rules deciding which entries below it are indexed
+#[derive(Debug, Clone)]
+pub struct Root {
+    /// Without a trailing `/`, unless it's `/` itself
+    pub path: String,
+    /// How deep below the root entries are indexed
+    pub depth: usize,
+    pub follow_symlinks: bool,
+    /// Whether entries starting with `.` are indexed
+    pub hidden: bool,
+    /// Whether entries excluded by `IGNORE_FILES` are skipped
+    pub ignore_files: bool,
+    /// Include and exclude globs, matched gitignore style relative to the root
+    overrides: Override,
+    /// The globs `overrides` was built from, excludes starting with `!`
+    globs: Vec<String>,
+}
+
+/// Roots are equal if they index the same entries
+impl PartialEq for Root {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.depth == other.depth
+            && self.follow_symlinks == other.follow_symlinks
+            && self.hidden == other.hidden
+            && self.ignore_files == other.ignore_files
+            && self.globs == other.globs
+    }
+}
+
+impl Root {
+    /// Reads a table of the `roots` config, fields it leaves out take their default
+    pub fn from_config(table: &Config, fields: &[ConfigField]) -> Result<Self, String> {
+        let mut config = config::defaults(fields);
+        config.extend(table.clone());
+        let path = config
+            .get("path")
+            .and_then(ConfigValue::as_str)
+            .ok_or("root has no path")?;
+        let path = normalize_root(path).to_string();
+        let list = |key: &str| {
+            config
+                .get(key)
+                .and_then(ConfigValue::as_list)
+                .unwrap_or_default()
+        };
+
+        // includes only narrow down files, directories are still descended into
+        let globs: Vec<String> = list("include")
+            .iter()
+            .cloned()
+            .chain(list("exclude").iter().map(|glob| format!("!{}", glob)))
+            .collect();
+        let mut overrides = OverrideBuilder::new(&path);
+        for glob in &globs {
+            overrides.add(glob).map_err(|error| error.to_string())?;
+        }
+        Ok(Self {
+            depth: config
+                .get("depth")
+                .and_then(ConfigValue::as_int)
+                .map(|depth| depth.max(0) as usize)
+                .unwrap_or(DEFAULT_DEPTH),
+            follow_symlinks: config
+                .get("follow_symlinks")
+                .and_then(ConfigValue::as_bool)
+                .unwrap_or(true),
+            hidden: config
+                .get("hidden")
+                .and_then(ConfigValue::as_bool)
+                .unwrap_or(false),
+            ignore_files: config
+                .get("ignore_files")
+                .and_then(ConfigValue::as_bool)
+                .unwrap_or(true),
+            overrides: overrides.build().map_err(|error| error.to_string())?,
+            globs,
+            path,
+        })
+    }
+
+    /// Indexes everything below `directory`, which lies `depth` levels below the root
+    pub fn index_directory(&self, directory: &Path, depth: usize) -> Vec<Entry> {
+        self.walk(directory, self.depth.saturating_sub(depth))
+    }
+
+    /// The entries directly inside `directory`
+    pub fn list_directory(&self, directory: &Path) -> Vec<Entry> {
+        self.walk(directory, 1)
+    }
+
+    /// `directory`, which lies `depth` levels below the root, and the directories below it whose
+    /// children are indexed. Watching them sees every change to indexed entries.
+    pub fn directories_to_watch(&self, directory: &Path, depth: usize) -> Vec<PathBuf> {
+        if depth >= self.depth {
+            return vec![];
+        }
+        let mut directories = vec![directory.to_path_buf()];
+        directories.extend(
+            self.walk(directory, self.depth - depth - 1)
+                .into_iter()
+                .filter(|entry| entry.kind == EntryKind::Directory)
+                .map(|entry| PathBuf::from(entry.path)),
+        );
+        directories
+    }
+
+    /// Whether `directory` is one of the directories to watch, its children being indexed
+    pub fn watches(&self, directory: &Path) -> bool {
+        self.depth_of(directory, true)
+            .is_some_and(|depth| depth < self.depth)
+    }
+
+    /// Reads the entry at `path` and how many levels below the root it lies, `None` if it
+    /// doesn't exist or isn't indexed under this root
+    pub fn entry(&self, path: &Path) -> Option<(Entry, usize)> {
+        let entry = Entry::from_path(path, self.follow_symlinks)?;
+        let depth = self.depth_of(path, entry.kind == EntryKind::Directory)?;
+        (depth > 0).then_some((entry, depth))
+    }
+
+    /// How many levels below the root `path` lies, `None` if it's outside the root, excluded or
+    /// deeper than the indexer goes
+    pub fn depth_of(&self, path: &Path, is_dir: bool) -> Option<usize> {
+        let components: Vec<_> = path.strip_prefix(&self.path).ok()?.components().collect();
+        let ignore_files = if self.ignore_files {
+            IgnoreFiles::read(path)
+        } else {
+            IgnoreFiles::default()
+        };
+        let mut current = PathBuf::from(&self.path);
+        for (index, component) in components.iter().enumerate() {
+            current.push(component);
+            // everything above the path itself is a directory
+            let is_dir = is_dir || index + 1 < components.len();
+            // same precedence as the walker: globs, then ignore files, whitelisted entries are
+            // indexed even if they're hidden
+            let matched = match self.overrides.matched(&current, is_dir).map(|_| ()) {
+                Match::None => ignore_files.matched(&current, is_dir),
+                matched => matched,
+            };
+            let hidden = !self.hidden && is_hidden(&component.as_os_str().to_string_lossy());
+            if matched.is_ignore() || (matched.is_none() && hidden) {
+                return None;
+            }
+        }
+        (components.len() <= self.depth).then_some(components.len())
+    }
+
+    fn walk(&self, directory: &Path, max_depth: usize) -> Vec<Entry> {
+        if max_depth == 0 {
+            return vec![];
+        }
+        self.walker(directory, max_depth).collect()
+    }
+
+    fn walker(&self, directory: &Path, max_depth: usize) -> impl Iterator<Item = Entry> + '_ {
+        let mut walk = WalkBuilder::new(directory);
+        walk.standard_filters(false)
+            .hidden(!self.hidden)
+            .follow_links(self.follow_symlinks)
+            .max_depth(Some(max_depth))
+            .overrides(self.overrides.clone());
+        if self.ignore_files {
+            // ignore files above `directory` apply too, `.gitignore` even outside repositories
+            walk.parents(true)
+                .ignore(true)
+                .git_ignore(true)
+                .require_git(false)
+                .add_custom_ignore_filename(IGNORE_FILES[0]);
+        }
+        walk.build()
             .filter_map(Result::ok)
-        {
-            let metadata = entry.metadata();
-            if let Ok(metadata) = metadata {
-                let kind = if metadata.is_file() {
-                    EntryKind::File
-                } else if metadata.is_dir() {
-                    EntryKind::Directory
-                } else {
-                    EntryKind::Symlink
-                };
-                entries.push(Entry {
-                    name: entry.file_name().to_string_lossy().to_string(),
-                    path: entry.path().to_string_lossy().to_string(),
-                    kind,
-                    ctime: metadata.ctime(),
-                    mtime: metadata.mtime(),
-                    atime: metadata.atime(),
+            .filter(|entry| entry.depth() > 0)
+            .filter_map(|entry| Entry::from_path(entry.path(), self.follow_symlinks))
+    }
+}
+
+/// The ignore files of every directory above a path, nearest first, a matcher per file in
+/// `IGNORE_FILES`
+#[derive(Default)]
+struct IgnoreFiles(Vec<Vec<Gitignore>>);
+
+impl IgnoreFiles {
+    fn read(path: &Path) -> Self {
+        Self(
+            path.ancestors()
+                .skip(1)
+                .map(|directory| {
+                    // missing files leave the matcher empty
+                    IGNORE_FILES
+                        .iter()
+                        .map(|name| Gitignore::new(directory.join(name)).0)
+                        .collect()
                 })
+                .collect(),
+        )
+    }
+
+    /// For each kind of file the nearest one mentioning the path decides
+    fn matched(&self, path: &Path, is_dir: bool) -> Match<()> {
+        let mut matched = [Match::None, Match::None, Match::None];
+        for matchers in &self.0 {
+            for (kind, matcher) in matchers.iter().enumerate() {
+                let above = path.starts_with(matcher.path()) && path != matcher.path();
+                if above && matched[kind].is_none() {
+                    matched[kind] = matcher.matched(path, is_dir).map(|_| ());
+                }
             }
         }
+        let [custom, ignore, git] = matched;
+        custom.or(ignore).or(git)
+    }
+}
 
-        // index all applications
-        for entry in WalkDir::new("/Applications/")
-            .min_depth(1)
-            .max_depth(1)
+fn normalize_root(root: &str) -> &str {
+    if root.len() > 1 {
+        root.trim_end_matches('/')
+    } else {
+        root
+    }
+}
+
+pub struct Fs {}
+
+impl Fs {
+    /// Indexes every root, `None` if `cancelled` was set meanwhile
+    pub fn index_file_system(roots: &[Root], cancelled: &AtomicBool) -> Option<Vec<Entry>> {
+        // index files, entries below several roots are indexed once per root
+        let mut entries = vec![];
+        for root in roots {
+            for entry in root.walker(Path::new(&root.path), root.depth) {
+                if cancelled.load(Ordering::Relaxed) {
+                    return None;
+                }
+                entries.push(entry);
+            }
+        }
+        Some(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Tree(tempfile::TempDir);
+
+    impl Tree {
+        /// Creates files with their contents, paths ending with `/` are created as directories
+        fn new(files: &[(&str, &str)]) -> Self {
+            let tree = Self(tempfile::tempdir().expect("Failed to create temporary directory"));
+            for (path, contents) in files {
+                let path = tree.path(path);
+                if path.to_string_lossy().ends_with('/') {
+                    std::fs::create_dir_all(&path).expect("Failed to create directory");
+                } else {
+                    std::fs::create_dir_all(path.parent().expect("Paths lie below the tree"))
+                        .expect("Failed to create directory");
+                    std::fs::write(&path, contents).expect("Failed to create file");
+                }
+            }
+            tree
+        }
+
+        fn path(&self, path: &str) -> PathBuf {
+            self.0.path().join(path)
+        }
+
+        fn root(&self, path: &str, config: &[(&str, ConfigValue)]) -> Root {
+            let mut table = Config::from([(
+                "path".to_string(),
+                ConfigValue::String(self.path(path).to_string_lossy().to_string()),
+            )]);
+            table.extend(
+                config
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone())),
+            );
+            Root::from_config(&table, &[]).expect("Failed to read the root")
+        }
+
+        fn depth_of(&self, root: &Root, path: &str) -> Option<usize> {
+            let path = self.path(path);
+            root.depth_of(&path, path.is_dir())
+        }
+    }
+
+    fn globs(globs: &[&str]) -> ConfigValue {
+        ConfigValue::List(globs.iter().map(|glob| glob.to_string()).collect())
+    }
+
+    #[test]
+    fn counts_levels_below_the_root_up_to_its_depth() {
+        let tree = Tree::new(&[("root/a/b/c/file", "")]);
+        let root = tree.root("root", &[("depth", ConfigValue::Int(2))]);
+        assert_eq!(tree.depth_of(&root, "root"), Some(0));
+        assert_eq!(tree.depth_of(&root, "root/a"), Some(1));
+        assert_eq!(tree.depth_of(&root, "root/a/b"), Some(2));
+        assert_eq!(tree.depth_of(&root, "root/a/b/c"), None);
+        assert_eq!(tree.depth_of(&root, "root/a/b/c/file"), None);
+        assert_eq!(tree.depth_of(&root, "elsewhere"), None);
+        // a sibling sharing the root's name as a prefix lies outside of it
+        assert_eq!(tree.depth_of(&root, "root2"), None);
+    }
+
+    #[test]
+    fn nested_roots_apply_their_own_rules() {
+        let tree = Tree::new(&[
+            ("home/notes/todo.md", ""),
+            ("home/notes/todo.txt", ""),
+            ("home/notes/old/done.md", ""),
+        ]);
+        let home = tree.root("home", &[]);
+        let notes = tree.root(
+            "home/notes",
+            &[
+                ("depth", ConfigValue::Int(1)),
+                ("include", globs(&["*.md"])),
+            ],
+        );
+        assert_eq!(tree.depth_of(&home, "home/notes/todo.txt"), Some(2));
+        assert_eq!(tree.depth_of(&home, "home/notes/old/done.md"), Some(3));
+        assert_eq!(tree.depth_of(&notes, "home/notes/todo.md"), Some(1));
+        assert_eq!(tree.depth_of(&notes, "home/notes/todo.txt"), None);
+        assert_eq!(tree.depth_of(&notes, "home/notes/old/done.md"), None);
+    }
+
+    #[test]
+    fn skips_hidden_and_excluded_entries_and_what_lies_below_them() {
+        let tree = Tree::new(&[
+            ("root/.config/file", ""),
+            ("root/node_modules/lib/index.js", ""),
+            ("root/src/main.rs", ""),
+        ]);
+        let root = tree.root("root", &[("exclude", globs(&["node_modules"]))]);
+        assert_eq!(tree.depth_of(&root, "root/.config"), None);
+        assert_eq!(tree.depth_of(&root, "root/.config/file"), None);
+        assert_eq!(tree.depth_of(&root, "root/node_modules/lib/index.js"), None);
+        assert_eq!(tree.depth_of(&root, "root/src/main.rs"), Some(2));
+
+        let hidden = tree.root("root", &[("hidden", ConfigValue::Bool(true))]);
+        assert_eq!(tree.depth_of(&hidden, "root/.config/file"), Some(2));
+    }
+
+    #[test]
+    fn skips_entries_excluded_by_each_ignore_file() {
+        for name in IGNORE_FILES {
+            let tree = Tree::new(&[
+                (&format!("root/{name}"), "*.log\nbuild/\n"),
+                ("root/debug.log", ""),
+                ("root/build/out", ""),
+                ("root/main.rs", ""),
+            ]);
+            let root = tree.root("root", &[]);
+            assert_eq!(tree.depth_of(&root, "root/debug.log"), None, "{name}");
+            assert_eq!(tree.depth_of(&root, "root/build/out"), None, "{name}");
+            assert_eq!(tree.depth_of(&root, "root/main.rs"), Some(1), "{name}");
+
+            let unfiltered = tree.root("root", &[("ignore_files", ConfigValue::Bool(false))]);
+            assert_eq!(
+                tree.depth_of(&unfiltered, "root/debug.log"),
+                Some(1),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn negated_patterns_include_entries_again() {
+        let tree = Tree::new(&[
+            ("root/.gitignore", "*.log\n!keep.log\n"),
+            ("root/debug.log", ""),
+            ("root/keep.log", ""),
+            ("root/logs/.gitignore", "!*.log\n"),
+            ("root/logs/today.log", ""),
+        ]);
+        let root = tree.root("root", &[]);
+        assert_eq!(tree.depth_of(&root, "root/debug.log"), None);
+        assert_eq!(tree.depth_of(&root, "root/keep.log"), Some(1));
+        // the nearest ignore file mentioning the entry decides
+        assert_eq!(tree.depth_of(&root, "root/logs/today.log"), Some(2));
+    }
+
+    #[test]
+    fn ignore_files_take_precedence_in_order() {
+        let tree = Tree::new(&[
+            ("root/.gitignore", "*.log\n*.tmp\n"),
+            ("root/.ignore", "!*.log\n*.bak\n"),
+            ("root/.finignore", "!*.bak\n"),
+            ("root/a.log", ""),
+            ("root/a.tmp", ""),
+            ("root/a.bak", ""),
+        ]);
+        let root = tree.root("root", &[]);
+        assert_eq!(tree.depth_of(&root, "root/a.log"), Some(1));
+        assert_eq!(tree.depth_of(&root, "root/a.tmp"), None);
+        assert_eq!(tree.depth_of(&root, "root/a.bak"), Some(1));
+    }
+
+    #[test]
+    fn applies_ignore_files_above_the_root_and_globs_over_them() {
+        let tree = Tree::new(&[
+            (".gitignore", "*.log\n"),
+            ("root/debug.log", ""),
+            ("root/trace.log", ""),
+        ]);
+        assert_eq!(
+            tree.depth_of(&tree.root("root", &[]), "root/debug.log"),
+            None
+        );
+        let root = tree.root("root", &[("include", globs(&["trace.log"]))]);
+        assert_eq!(tree.depth_of(&root, "root/trace.log"), Some(1));
+    }
+
+    #[test]
+    fn agrees_with_the_walker() {
+        let tree = Tree::new(&[
+            ("root/.gitignore", "*.log\n!keep.log\n"),
+            ("root/.ignore", "skipped/\n"),
+            ("root/keep.log", ""),
+            ("root/debug.log", ""),
+            ("root/skipped/file", ""),
+            ("root/a/b/c/deep", ""),
+            ("root/.hidden/file", ""),
+            ("root/target/out", ""),
+        ]);
+        let root = tree.root(
+            "root",
+            &[
+                ("depth", ConfigValue::Int(3)),
+                ("exclude", globs(&["target"])),
+            ],
+        );
+        let mut walked: Vec<String> = root
+            .index_directory(&tree.path("root"), 0)
             .into_iter()
-            .filter_entry(|entry| {
-                let file_name_substrings: Vec<String> = entry
-                    .file_name()
-                    .to_string_lossy()
-                    .split(".")
-                    .into_iter()
-                    .map(|substring| substring.to_string())
-                    .collect();
-                file_name_substrings
-                    .get(file_name_substrings.len() - 1)
-                    .unwrap()
-                    .contains("app")
-            })
-            .filter_map(Result::ok)
-        {
-            let metadata = entry.metadata().unwrap();
-            entries.push(Entry {
-                name: entry.file_name().to_string_lossy().to_string(),
-                path: entry.path().to_string_lossy().to_string(),
-                kind: EntryKind::Application,
-                ctime: metadata.ctime(),
-                mtime: metadata.mtime(),
-                atime: metadata.atime(),
-            })
-        }
-        entries
+            .map(|entry| entry.path)
+            .collect();
+        walked.sort();
+        let indexed = ["root/a", "root/a/b", "root/a/b/c", "root/keep.log"];
+        assert_eq!(
+            walked,
+            indexed.map(|path| tree.path(path).to_string_lossy().to_string())
+        );
+        let skipped = [
+            "root/a/b/c/deep",
+            "root/debug.log",
+            "root/skipped",
+            "root/skipped/file",
+            "root/.hidden",
+            "root/.hidden/file",
+            "root/target",
+            "root/target/out",
+            "root/.gitignore",
+        ];
+        for path in indexed {
+            assert!(tree.depth_of(&root, path).is_some(), "{path}");
+        }
+        for path in skipped {
+            assert_eq!(tree.depth_of(&root, path), None, "{path}");
+        }
     }
 }
@@ -0,0 +1,200 @@
+use crate::{cache, db, db::fs::Root, watcher::Watcher};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the cache is compared with the filesystem, for changes the watcher missed
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How often all roots are scanned again, sweeping entries reconciling didn't notice
+const FULL_SCAN_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+enum Message {
+    /// Index these roots from now on, `rescan` sweeps the entries their rules no longer include
+    ChangeRoots { roots: Vec<Root>, rescan: bool },
+    /// Entries a search found missing on disk
+    RemoveStale(Vec<String>),
+    /// Answers once every message sent before it was handled
+    Flush(mpsc::Sender<()>),
+}
+
+/// Keeps the cache in line with the filesystem on a thread of its own, sharing the pool with
+/// searches so they never wait for a scan. Dropping it cancels a running scan and waits for the
+/// thread to finish, so a replacing indexer never writes to the cache alongside it.
+pub struct Indexer {
+    sender: Option<mpsc::Sender<Message>>,
+    cancelled: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Indexer {
+    /// Indexes the cache's roots if it's empty, then reconciles them periodically
+    pub fn start(db: db::Db, cache: cache::Cache, runtime: tokio::runtime::Handle) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = Arc::clone(&cancelled);
+        let thread = std::thread::spawn(move || {
+            // without a pool indexing fails as well and says so
+            let watcher = db.pool().ok().and_then(|pool| {
+                match Watcher::start(pool.clone(), runtime.clone()) {
+                    Ok(watcher) => Some(watcher),
+                    // reconciling still picks changes up, just later
+                    Err(error) => {
+                        println!("Unable to watch the filesystem: {}", error);
+                        None
+                    }
+                }
+            });
+            let mut state = State {
+                db,
+                cache,
+                runtime,
+                watcher,
+                cancelled: thread_cancelled,
+                last_reconciled: Instant::now(),
+                last_full_scan: Instant::now(),
+            };
+            if let Err(error) = state
+                .runtime
+                .block_on(state.cache.init(&state.db, &state.cancelled))
+            {
+                println!("Failed to index the filesystem: {}", error);
+            }
+            loop {
+                let timeout = RECONCILE_INTERVAL.saturating_sub(state.last_reconciled.elapsed());
+                let result = match receiver.recv_timeout(timeout) {
+                    Ok(Message::ChangeRoots { roots, rescan }) => state.change_roots(roots, rescan),
+                    Ok(Message::RemoveStale(paths)) => state.remove_stale(&paths),
+                    Ok(Message::Flush(done)) => {
+                        let _ = done.send(());
+                        Ok(())
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => state.reconcile(),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                };
+                if let Err(error) = result {
+                    println!("Failed to update the filesystem cache: {}", error);
+                }
+            }
+        });
+        Self {
+            sender: Some(sender),
+            cancelled,
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, message: Message) -> bool {
+        self.sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(message).is_ok())
+    }
+
+    /// Indexes `roots` instead. On startup the cache is mostly up to date already and is only
+    /// reconciled, changed rules may exclude cached entries and need a full scan to sweep them.
+    pub fn change_roots(&self, roots: Vec<Root>, rescan: bool) {
+        self.send(Message::ChangeRoots { roots, rescan });
+    }
+
+    /// Removes entries that no longer exist, along with everything below them
+    pub fn remove_stale(&self, paths: Vec<String>) {
+        self.send(Message::RemoveStale(paths));
+    }
+
+    /// Blocks until the changes sent so far are in the cache
+    pub fn flush(&self) {
+        let (done, receiver) = mpsc::channel();
+        if self.send(Message::Flush(done)) {
+            let _ = receiver.recv();
+        }
+    }
+}
+
+impl Drop for Indexer {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        // disconnects the channel, stopping the thread once it's done with the current message
+        self.sender = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct State {
+    db: db::Db,
+    cache: cache::Cache,
+    runtime: tokio::runtime::Handle,
+    watcher: Option<Watcher>,
+    /// Set once the indexer is dropped, long running scans stop early
+    cancelled: Arc<AtomicBool>,
+    last_reconciled: Instant,
+    last_full_scan: Instant,
+}
+
+impl State {
+    fn change_roots(&mut self, roots: Vec<Root>, rescan: bool) -> plugin_api::Result<()> {
+        // watch first, so nothing changing while the cache catches up is missed
+        if let Some(watcher) = &self.watcher {
+            watcher.change_roots(roots.clone());
+        }
+        self.last_reconciled = Instant::now();
+        if rescan {
+            self.cache.roots = roots;
+            self.full_scan()
+        } else {
+            self.runtime
+                .block_on(self.cache.change_roots(&self.db, roots, &self.cancelled))
+        }
+    }
+
+    fn remove_stale(&self, paths: &[String]) -> plugin_api::Result<()> {
+        let pool = self.db.pool()?;
+        self.runtime.block_on(async {
+            for path in paths {
+                cache::remove_subtree(pool, path).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn reconcile(&mut self) -> plugin_api::Result<()> {
+        self.last_reconciled = Instant::now();
+        if self.last_full_scan.elapsed() >= FULL_SCAN_INTERVAL {
+            let result = self.full_scan();
+            // the scan may have come across directories that appeared unnoticed
+            if let Some(watcher) = &self.watcher {
+                for root in &self.cache.roots {
+                    watcher.watch(&root.directories_to_watch(Path::new(&root.path), 0));
+                }
+            }
+            return result;
+        }
+
+        let pool = self.db.pool()?;
+        let roots = &self.cache.roots;
+        let appeared = self.runtime.block_on(async {
+            let mut appeared = vec![];
+            for root in roots {
+                appeared.extend(cache::reconcile(pool, roots, root, &self.cancelled).await?);
+            }
+            Ok::<_, plugin_api::Error>(appeared)
+        })?;
+        if let Some(watcher) = &self.watcher {
+            watcher.watch(&appeared);
+        }
+        Ok(())
+    }
+
+    fn full_scan(&mut self) -> plugin_api::Result<()> {
+        self.last_full_scan = Instant::now();
+        self.runtime.block_on(
+            self.cache
+                .cache_file_system(&self.db, true, &self.cancelled),
+        )
+    }
+}
@@ -1,43 +1,50 @@
 mod cache;
 mod db;
+mod indexer;
+mod watcher;
 
-use plugin_api::Plugin;
-use sqlx::Row;
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+use plugin_api::{
+    config::{self, Config, ConfigField, ConfigKind, ConfigValue},
+    fuzzy, Plugin,
 };
+use sqlx::Row;
+use std::sync::{Arc, Mutex};
+
+/// Entries fetched from the cache for fuzzy scoring
+const CANDIDATE_LIMIT: usize = 5000;
+/// Entries fetched when the terms only match across directories, the scan visits every row
+const PATH_CANDIDATE_LIMIT: usize = 500;
+/// The trigram index only finds terms at least this long
+const MIN_INDEXED_TERM_LENGTH: usize = 3;
+const RESULT_LIMIT: usize = 100;
 
 #[derive(Clone)]
 pub struct FsPlugin {
-    results: Option<Vec<db::fs::Entry>>,
     db: db::Db,
-    cache: cache::Cache,
-    client_state: Arc<Mutex<plugin_api::ClientState>>,
     loaded_plugin: Option<Arc<Mutex<plugin_api::LoadedPlugin>>>,
-    config: HashMap<String, String>,
-    last_query: String,
+    runtime: Option<tokio::runtime::Handle>,
+    indexer: Option<Arc<indexer::Indexer>>,
+    /// The `roots` config the cache was last brought in line with
+    roots_config: Option<ConfigValue>,
+    default_path: String,
 }
 
 impl Default for FsPlugin {
     fn default() -> Self {
-        let mut config: HashMap<String, String> = HashMap::new();
-        let path = directories::BaseDirs::new()
+        let default_path = directories::BaseDirs::new()
             .expect("Failed to get base directories")
             .home_dir()
             .to_str()
             .expect("Failed to convert home directory to string")
             .to_string();
-        config.insert("path".to_string(), path);
 
         Self {
             db: db::Db::default(),
-            cache: cache::Cache::default(),
-            results: Some(Vec::new()),
-            client_state: Arc::new(Mutex::new(plugin_api::ClientState::default())),
             loaded_plugin: None,
-            config,
-            last_query: String::new(),
+            runtime: None,
+            indexer: None,
+            roots_config: None,
+            default_path,
         }
     }
 }
@@ -45,96 +52,132 @@ impl Default for FsPlugin {
 impl Plugin for FsPlugin {
     fn init(
         &mut self,
-        client_state_arc: Arc<Mutex<plugin_api::ClientState>>,
         loaded_plugin: Arc<Mutex<plugin_api::LoadedPlugin>>,
-    ) {
-        self.client_state = client_state_arc;
+    ) -> plugin_api::Result<()> {
+        let (runtime, config) = {
+            let loaded_plugin = plugin_api::lock(&loaded_plugin);
+            (loaded_plugin.runtime.clone(), loaded_plugin.config.clone())
+        };
         self.loaded_plugin = Some(loaded_plugin);
         // self.db.init(Some(
         //     "/Users/athulanoop/.config/fin/cache.sqlite".to_string(),
         // ));
-        self.db.init(None);
+        self.db.init(None, &runtime)?;
 
-        tokio::runtime::Runtime::new()
-            .expect("Failed to create Tokio runtime")
-            .block_on(self.cache.init(&self.db));
+        let cache = cache::Cache {
+            roots: self.roots(&config),
+            ..Default::default()
+        };
+        self.indexer = Some(Arc::new(indexer::Indexer::start(
+            self.db.clone(),
+            cache,
+            runtime.clone(),
+        )));
+        self.runtime = Some(runtime);
 
         println!("Calculator plugin initialized!");
+        Ok(())
+    }
+
+    fn start(&mut self) -> plugin_api::Result<()> {
+        let (Some(loaded_plugin), Some(indexer)) = (&self.loaded_plugin, &self.indexer) else {
+            return Ok(());
+        };
+        let config = plugin_api::lock(loaded_plugin).config.clone();
+        let roots_config = config.get("roots").cloned();
+        if self.roots_config != roots_config {
+            dbg!("Re-indexing filesystem with config: {:?}", &config);
+            indexer.change_roots(self.roots(&config), self.roots_config.is_some());
+            self.roots_config = roots_config;
+        }
+        Ok(())
     }
 
-    fn start(&mut self) {
-        let mut client_state = self
-            .client_state
-            .lock()
-            .expect("Failed to lock client state");
-
-        let query = client_state.get_search_query();
-        if let Some(loaded_plugin) = &self.loaded_plugin {
-            let config = loaded_plugin
-                .lock()
-                .expect("Failed to lock loaded plugin")
-                .config
-                .clone();
-            if let Some(path) = config.get("path") {
-                if let Some(existing_path) = &self.cache.filesystem_root {
-                    if existing_path.ne(path) {
-                        self.cache.filesystem_root = Some(path.to_string());
-                        dbg!("Re-indexing filesystem with config: {:?}", config);
-                        tokio::runtime::Runtime::new()
-                            .expect("Failed to create Tokio runtime")
-                            .block_on(self.cache.cache_file_system(&self.db, true, true))
-                    } else {
-                    }
-                } else {
-                    self.cache.filesystem_root = Some(path.to_string());
-                    dbg!("Re-indexing filesystem with config: {:?}", config);
-                    tokio::runtime::Runtime::new()
-                        .expect("Failed to create Tokio runtime")
-                        .block_on(self.cache.cache_file_system(&self.db, true, true))
+    fn search(&mut self, query: &plugin_api::Query) -> Vec<plugin_api::SearchResult> {
+        let candidates = match self.get_files(&query.text) {
+            Ok(candidates) => candidates,
+            Err(error) => {
+                println!("FS Walk search failed: {}", error);
+                return vec![];
+            }
+        };
+        let mut results: Vec<(db::fs::Entry, fuzzy::Match)> = candidates
+            .into_iter()
+            .filter_map(|entry| {
+                fuzzy::fuzzy_match(&query.text, &entry.path).map(|matched| (entry, matched))
+            })
+            .collect();
+        results.sort_by(|(a, a_match), (b, b_match)| {
+            b_match
+                .score
+                .cmp(&a_match.score)
+                .then_with(|| a.path.len().cmp(&b.path.len()))
+        });
+        // the cache lags behind the filesystem, entries that are gone are hidden and dropped
+        let mut stale = vec![];
+        let results: Vec<(db::fs::Entry, fuzzy::Match)> = results
+            .into_iter()
+            .filter(|(entry, _)| {
+                let exists = std::path::Path::new(&entry.path).exists();
+                if !exists {
+                    stale.push(entry.path.clone());
                 }
+                exists
+            })
+            .take(RESULT_LIMIT)
+            .collect();
+        if !stale.is_empty() {
+            if let Some(indexer) = &self.indexer {
+                indexer.remove_stale(stale);
             }
         }
-        if self.last_query == query {
-            return;
-        } else {
-            self.last_query = query.to_string();
-        }
-        let result = self.get_files(query);
-        if let Ok(result) = result {
-            self.results = Some(result);
-        } else {
-            self.results = None;
-        }
-        if let Some(results) = &self.results {
-            let existing_results = client_state.get_search_results();
-            let mut new_results = Vec::new();
-            for result in existing_results {
-                new_results.push(result);
-            }
-            for result in results {
+
+        results
+            .iter()
+            .map(|(result, matched)| {
                 let icon = match result.kind.as_str() {
-                    "application" => None,
                     "directory" => Some(plugin_api::Icon::Folder),
                     "file" => Some(plugin_api::Icon::File),
                     _ => None,
                 };
-                let action = match result.kind.as_str() {
-                    "application" => {
-                        Some(plugin_api::Action::LaunchApplication(result.path.clone()))
-                    }
-                    "directory" | "file" => Some(plugin_api::Action::Open(result.path.clone())),
-                    _ => None,
+                let (action, actions) = match result.kind.as_str() {
+                    "directory" | "file" => (
+                        Some(plugin_api::Action::Open(result.path.clone())),
+                        vec![
+                            plugin_api::ResultAction::new(
+                                "Show in file manager",
+                                plugin_api::Action::RevealInFileManager(result.path.clone()),
+                            )
+                            .with_shortcut("Ctrl+Enter"),
+                            plugin_api::ResultAction::new(
+                                "Copy path",
+                                plugin_api::Action::CopyText(result.path.clone()),
+                            )
+                            .with_shortcut("Ctrl+C"),
+                        ],
+                    ),
+                    _ => (None, vec![]),
                 };
-                new_results.push(plugin_api::SearchResult::new(
+                // the name is the last segment of the path, shift the ranges that fall into it
+                let name_start = result.path.chars().count() - result.name.chars().count();
+                let title_matches = matched
+                    .ranges
+                    .iter()
+                    .filter(|range| range.end > name_start)
+                    .map(|range| range.start.max(name_start) - name_start..range.end - name_start)
+                    .collect();
+                plugin_api::SearchResult::new(
                     result.name.clone(),
-                    None,
+                    Some(result.path.clone()),
                     icon,
                     action,
                     Some(10),
-                ));
-            }
-            client_state.update_search_results(new_results);
-        }
+                )
+                .with_score(matched.normalized_score())
+                .with_matches(title_matches, matched.ranges.clone())
+                .with_actions(actions)
+            })
+            .collect()
     }
     fn get_metadata(&self) -> plugin_api::Metadata {
         plugin_api::Metadata {
@@ -145,44 +188,230 @@ impl Plugin for FsPlugin {
         }
     }
     fn destroy(&mut self) {
+        self.indexer = None;
         println!("Calculator plugin destroyed!");
     }
     fn clone_box(&self) -> Box<dyn Plugin> {
         Box::new(self.clone())
     }
 
-    fn get_config(&self) -> std::collections::HashMap<String, String> {
-        self.config.clone()
+    fn get_config_schema(&self) -> plugin_api::config::ConfigSchema {
+        let fields = self.root_fields();
+        vec![ConfigField::new(
+            "roots",
+            ConfigKind::TableList {
+                fields: fields.clone(),
+            },
+            ConfigValue::Tables(vec![config::defaults(&fields)]),
+            "Directories to index",
+        )]
     }
 }
 
 impl FsPlugin {
-    fn get_files(&self, filter: &str) -> Result<Vec<db::fs::Entry>, String> {
-        let filter = format!("%{filter}%");
-        let files = tokio::runtime::Runtime::new()
-            .expect("Failed to create Tokio runtime")
-            .block_on(async {
-                let records = sqlx::query(
-                    "SELECT * FROM filesystem WHERE name LIKE $1 OR path LIKE $2 ORDER BY CASE WHEN kind = 'application' THEN 0 ELSE 1 END ,atime DESC LIMIT 100",
-                )
-                .bind(&filter)
-                .bind(&filter)
-                .fetch_all(self.db.pool.as_ref().expect("SQLite connection pool must be valid to query database"))
-                .await
-                .unwrap();
-                return records
+    /// Blocks until the roots given so far are indexed, searches only find what's cached
+    pub fn wait_until_indexed(&self) {
+        if let Some(indexer) = &self.indexer {
+            indexer.flush();
+        }
+    }
+
+    /// The fields of each table in the `roots` config
+    fn root_fields(&self) -> Vec<ConfigField> {
+        vec![
+            ConfigField::new(
+                "path",
+                ConfigKind::Path {
+                    must_exist: true,
+                    directory: true,
+                },
+                ConfigValue::String(self.default_path.clone()),
+                "Directory to index",
+            ),
+            ConfigField::new(
+                "depth",
+                ConfigKind::Int {
+                    min: Some(1),
+                    max: Some(32),
+                },
+                ConfigValue::Int(db::fs::DEFAULT_DEPTH as i64),
+                "How many levels below the directory are indexed",
+            ),
+            ConfigField::new(
+                "follow_symlinks",
+                ConfigKind::Bool,
+                ConfigValue::Bool(true),
+                "Index what symlinks point to instead of the links themselves",
+            ),
+            ConfigField::new(
+                "hidden",
+                ConfigKind::Bool,
+                ConfigValue::Bool(false),
+                "Index entries starting with `.`",
+            ),
+            ConfigField::new(
+                "ignore_files",
+                ConfigKind::Bool,
+                ConfigValue::Bool(true),
+                "Skip what `.gitignore`, `.ignore` and `.finignore` files exclude",
+            ),
+            ConfigField::new(
+                "include",
+                ConfigKind::StringList,
+                ConfigValue::List(vec![]),
+                "Globs of the files to index, all of them if empty",
+            ),
+            ConfigField::new(
+                "exclude",
+                ConfigKind::StringList,
+                ConfigValue::List(vec![
+                    "node_modules".to_string(),
+                    "target".to_string(),
+                    ".git".to_string(),
+                ]),
+                "Globs of the files and directories to skip",
+            ),
+        ]
+    }
+
+    fn roots(&self, config: &Config) -> Vec<db::fs::Root> {
+        let fields = self.root_fields();
+        config
+            .get("roots")
+            .and_then(ConfigValue::as_tables)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|table| match db::fs::Root::from_config(table, &fields) {
+                Ok(root) => Some(root),
+                Err(error) => {
+                    println!("Skipping FS Walk root: {}", error);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn runtime(&self) -> plugin_api::Result<tokio::runtime::Handle> {
+        self.runtime
+            .clone()
+            .ok_or_else(|| plugin_api::Error::other("FS Walk plugin is not initialised"))
+    }
+
+    /// Fetches candidates for the fuzzy matcher. Entries containing the terms are looked up in
+    /// the full text index, only when that finds nothing is the cache scanned for entries
+    /// containing the terms as a subsequence, e.g. `dwnlds` for `Downloads`.
+    fn get_files(&self, filter: &str) -> plugin_api::Result<Vec<db::fs::Entry>> {
+        let terms: Vec<&str> = filter.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
+        let (indexed, short): (Vec<&str>, Vec<&str>) = terms
+            .iter()
+            .partition(|term| term.chars().count() >= MIN_INDEXED_TERM_LENGTH);
+
+        if !indexed.is_empty() {
+            let expression = indexed
+                .iter()
+                .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+                .collect::<Vec<String>>()
+                .join(" AND ");
+            // file names are looked up first, matching the terms anywhere in the path finds
+            // far more rows and is only done when names don't fill the results. Neither is
+            // ordered, so the lookup stops at the limit instead of sorting every match.
+            let mut entries: Vec<db::fs::Entry> = vec![];
+            let mut listed = std::collections::HashSet::new();
+            for column in ["name", "path"] {
+                if entries.len() >= RESULT_LIMIT {
+                    break;
+                }
+                let mut arguments = vec![format!("{{{column}}} : ({expression})")];
+                arguments.extend(short.iter().map(|term| Self::subsequence_pattern(term)));
+                let conditions: String = short
                     .iter()
-                    .map(|record| db::fs::Entry{
+                    .map(|_| format!(" AND filesystem.{column} LIKE ? ESCAPE '\\'"))
+                    .collect();
+                let found = self.fetch_entries(
+                    &format!(
+                        "SELECT filesystem.* FROM filesystem_fts JOIN filesystem ON filesystem.id = filesystem_fts.rowid WHERE filesystem_fts MATCH ?{conditions} LIMIT {CANDIDATE_LIMIT}"
+                    ),
+                    &arguments,
+                )?;
+                entries.extend(
+                    found
+                        .into_iter()
+                        .filter(|entry| listed.insert(entry.path.clone())),
+                );
+            }
+            // substring matches outrank subsequence matches, a scan could only add worse ones
+            if !entries.is_empty() {
+                return Ok(entries);
+            }
+        }
+
+        // scans visit short paths first through `idx_path_length` and stop at the limit, file
+        // names are tried first as that's what the terms match most of the time
+        let arguments: Vec<String> = terms
+            .iter()
+            .map(|term| Self::subsequence_pattern(term))
+            .collect();
+        for (column, limit) in [("name", CANDIDATE_LIMIT), ("path", PATH_CANDIDATE_LIMIT)] {
+            let conditions =
+                vec![format!("{column} LIKE ? ESCAPE '\\'"); terms.len()].join(" AND ");
+            let entries = self.fetch_entries(
+                &format!(
+                    "SELECT * FROM filesystem WHERE {conditions} ORDER BY length(path) LIMIT {limit}"
+                ),
+                &arguments,
+            )?;
+            if !entries.is_empty() {
+                return Ok(entries);
+            }
+        }
+        Ok(vec![])
+    }
+
+    fn fetch_entries(
+        &self,
+        statement: &str,
+        arguments: &[String],
+    ) -> plugin_api::Result<Vec<db::fs::Entry>> {
+        let pool = self.db.pool()?;
+        self.runtime()?.block_on(async {
+            let mut query = sqlx::query(statement);
+            for argument in arguments {
+                query = query.bind(argument);
+            }
+            let records = query
+                .fetch_all(pool)
+                .await
+                .map_err(plugin_api::Error::other)?;
+            records
+                .iter()
+                .map(|record| {
+                    Ok(db::fs::Entry {
                         name: record.get("name"),
                         path: record.get("path"),
-                        kind: db::fs::EntryKind::from(record.get::<&str, _>("kind")),
+                        kind: db::fs::EntryKind::try_from(record.get::<&str, _>("kind"))
+                            .map_err(plugin_api::Error::Plugin)?,
                         ctime: record.get("ctime"),
                         mtime: record.get("mtime"),
                         atime: record.get("atime"),
                     })
-                    .collect::<Vec<db::fs::Entry>>();
+                })
+                .collect::<plugin_api::Result<Vec<db::fs::Entry>>>()
+        })
+    }
 
-            });
-        Ok(files)
+    /// `dwn` becomes `%d%w%n%`, LIKE is case-insensitive for ASCII like the fuzzy matcher
+    fn subsequence_pattern(term: &str) -> String {
+        let mut pattern = String::from("%");
+        for c in term.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+            pattern.push('%');
+        }
+        pattern
     }
 }
@@ -0,0 +1,141 @@
+use crate::{cache, db::fs::Root};
+use notify::Watcher as _;
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, Weak},
+    time::Duration,
+};
+
+/// How long to wait for more events before applying them, file operations come in bursts
+const DEBOUNCE: Duration = Duration::from_millis(200);
+/// Applies a burst early once it touched this many paths, so a steady stream can't stall it
+const MAX_BATCH: usize = 1000;
+
+/// Applies changes below the roots to the cache as they happen, until it's dropped. Only the
+/// directories whose children are indexed are watched, each on its own, so depth limits and
+/// excluded directories don't use up watches.
+pub struct Watcher {
+    watches: Arc<Mutex<Watches>>,
+    roots: Arc<Mutex<Vec<Root>>>,
+}
+
+struct Watches {
+    watcher: notify::RecommendedWatcher,
+    directories: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Starts without roots, they're given with `change_roots`
+    pub fn start(pool: sqlx::SqlitePool, runtime: tokio::runtime::Handle) -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let watches = Arc::new(Mutex::new(Watches {
+            watcher: notify::recommended_watcher(sender)?,
+            directories: HashSet::new(),
+        }));
+        let roots: Arc<Mutex<Vec<Root>>> = Arc::default();
+        // the thread only holds on to the watcher while adding watches, dropping `Self` drops it
+        let handle = Arc::downgrade(&watches);
+        let thread_roots = Arc::clone(&roots);
+
+        std::thread::spawn(move || {
+            // the channel disconnects once the watcher is dropped
+            while let Ok(event) = receiver.recv() {
+                let mut paths = BTreeSet::new();
+                collect(&mut paths, event);
+                while paths.len() < MAX_BATCH {
+                    match receiver.recv_timeout(DEBOUNCE) {
+                        Ok(event) => collect(&mut paths, event),
+                        Err(_) => break,
+                    }
+                }
+                if paths.is_empty() {
+                    continue;
+                }
+                let roots = plugin_api::lock(&thread_roots).clone();
+                match runtime.block_on(cache::sync_paths(&pool, &roots, &paths)) {
+                    Ok(directories) => {
+                        watch(&handle, &directories);
+                    }
+                    Err(error) => println!("Failed to apply filesystem changes: {}", error),
+                }
+            }
+        });
+        Ok(Self { watches, roots })
+    }
+
+    /// Watches `roots` instead. Only roots that were added or whose rules changed are listed,
+    /// directories no longer indexed by any root are unwatched.
+    pub fn change_roots(&self, roots: Vec<Root>) {
+        let previous = std::mem::replace(&mut *plugin_api::lock(&self.roots), roots.clone());
+        let removed: Vec<&Root> = previous
+            .iter()
+            .filter(|root| !roots.contains(root))
+            .collect();
+        if !removed.is_empty() {
+            let mut watches = plugin_api::lock(&self.watches);
+            let unwatched: Vec<PathBuf> = watches
+                .directories
+                .iter()
+                .filter(|directory| {
+                    removed.iter().any(|root| directory.starts_with(&root.path))
+                        && !roots.iter().any(|root| root.watches(directory))
+                })
+                .cloned()
+                .collect();
+            for directory in unwatched {
+                // fails for directories that were deleted, which dropped their watch already
+                let _ = watches.watcher.unwatch(&directory);
+                watches.directories.remove(&directory);
+            }
+        }
+        // listing the roots takes as long as indexing them, events queue up meanwhile
+        for root in roots.iter().filter(|root| !previous.contains(root)) {
+            if !watch(
+                &Arc::downgrade(&self.watches),
+                &root.directories_to_watch(Path::new(&root.path), 0),
+            ) {
+                return;
+            }
+        }
+    }
+
+    /// Watches directories that appeared without the watcher noticing, e.g. found reconciling
+    pub fn watch(&self, directories: &[PathBuf]) {
+        watch(&Arc::downgrade(&self.watches), directories);
+    }
+}
+
+/// Adds a watch for each directory, returning `false` if the watcher was dropped
+fn watch(watches: &Weak<Mutex<Watches>>, directories: &[PathBuf]) -> bool {
+    let Some(watches) = watches.upgrade() else {
+        return false;
+    };
+    let mut watches = plugin_api::lock(&watches);
+    for directory in directories {
+        // reconciling still picks changes up, just later
+        match watches
+            .watcher
+            .watch(directory, notify::RecursiveMode::NonRecursive)
+        {
+            Ok(()) => {
+                watches.directories.insert(directory.clone());
+            }
+            // every further watch would fail the same way
+            Err(error) if matches!(error.kind, notify::ErrorKind::MaxFilesWatch) => {
+                println!("Unable to watch {}: {}", directory.display(), error);
+                break;
+            }
+            Err(error) => println!("Unable to watch {}: {}", directory.display(), error),
+        }
+    }
+    true
+}
+
+fn collect(paths: &mut BTreeSet<PathBuf>, event: notify::Result<notify::Event>) {
+    match event {
+        Ok(event) if !event.kind.is_access() => paths.extend(event.paths),
+        Ok(_) => {}
+        Err(error) => println!("Filesystem watcher error: {}", error),
+    }
+}
@@ -1,13 +1,31 @@
-mod keymaps;
+pub mod keymaps;
 
 use directories::BaseDirs;
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use notify::Watcher;
+use plugin_api::config::ConfigValue;
+use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
 use tauri::Manager;
 use toml;
 
+/// Used until the user has a fin.toml of their own, relative to the app's resources
+const DEFAULT_CONFIG: &str = "./assets/default-config.toml";
+
+#[derive(serde::Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    plugins: HashMap<String, plugin_api::config::Config>,
+    #[serde(default = "keymaps::default_keymaps")]
+    keymaps: HashMap<String, String>,
+    #[serde(flatten)]
+    configs: HashMap<String, String>,
+}
+
 #[derive(serde::Serialize, Debug)]
 pub struct Config {
     configs: HashMap<String, String>,
+    plugins: HashMap<String, plugin_api::config::Config>,
+    keymaps: HashMap<String, String>,
+    keymap_errors: Vec<String>,
     source: PathBuf,
 }
 
@@ -16,38 +34,195 @@ impl Default for Config {
         let configs = HashMap::new();
         Self {
             configs,
-            source: PathBuf::from_str("./assets/default-config.toml")
-                .expect("Should be valid path string"),
+            plugins: HashMap::new(),
+            keymaps: keymaps::default_keymaps(),
+            keymap_errors: vec![],
+            source: PathBuf::from_str(DEFAULT_CONFIG).expect("Should be valid path string"),
         }
     }
 }
 
 impl Config {
     pub fn init(&mut self, app: &tauri::App) {
-        self.load_config(app);
-        keymaps::init(app);
+        if let Err(error) = self.load_config(app.handle()) {
+            println!("Using default config: {}", error);
+        }
+        self.keymap_errors = keymaps::init(app, &self.keymaps);
         dbg!(self);
     }
-    fn set_source(&mut self, app: &tauri::App) {
-        self.source = app
-            .path()
-            .resolve(&self.source, tauri::path::BaseDirectory::Resource)
-            .expect("Should be valid path");
-        if let Some(base_dirs) = BaseDirs::new() {
-            let home_dir = base_dirs.home_dir();
-            let source = home_dir.join(".config/fin/fin.toml");
-            if source.exists() {
-                self.source = source;
+    pub fn get_keymaps(&self) -> HashMap<String, String> {
+        self.keymaps.clone()
+    }
+    pub fn get_keymap_errors(&self) -> Vec<String> {
+        self.keymap_errors.clone()
+    }
+    pub fn set_keymap_errors(&mut self, keymap_errors: Vec<String>) {
+        self.keymap_errors = keymap_errors;
+    }
+    /// Reads the user's fin.toml if there is one, e.g. it may have been deleted since the last
+    /// load, and the bundled default config otherwise
+    fn set_source(&mut self, app_handle: &tauri::AppHandle) {
+        self.source = match Self::user_config_path().filter(|source| source.exists()) {
+            Some(source) => source,
+            None => app_handle
+                .path()
+                .resolve(DEFAULT_CONFIG, tauri::path::BaseDirectory::Resource)
+                .expect("Should be valid path"),
+        };
+    }
+    fn user_config_path() -> Option<PathBuf> {
+        BaseDirs::new().map(|base_dirs| base_dirs.home_dir().join(".config/fin/fin.toml"))
+    }
+    /// Reads the config from disk, leaving the current config untouched if it can't be parsed
+    pub fn load_config(&mut self, app_handle: &tauri::AppHandle) -> Result<(), String> {
+        self.set_source(app_handle);
+        let config_file_contents = std::fs::read_to_string(&self.source)
+            .map_err(|error| format!("Unable to read {}: {}", self.source.display(), error))?;
+        let parsed_config: ConfigFile = toml::from_str(&config_file_contents)
+            .map_err(|error| format!("{} is not valid toml: {}", self.source.display(), error))?;
+        self.configs = parsed_config.configs;
+        self.plugins = parsed_config.plugins;
+        self.keymaps = parsed_config.keymaps;
+        Ok(())
+    }
+    pub fn get_plugin_configs(&self) -> HashMap<String, plugin_api::config::Config> {
+        self.plugins.clone()
+    }
+    /// Writes a single plugin config value to the user's fin.toml, keeping the rest of the
+    /// file (comments and formatting included) untouched
+    pub fn save_plugin_config(
+        &mut self,
+        plugin_name: &str,
+        key: &str,
+        value: &ConfigValue,
+    ) -> Result<(), String> {
+        let target = Self::user_config_path().ok_or("Unable to locate the home directory")?;
+        // the bundled default config is the starting point until the user has their own file
+        let contents = std::fs::read_to_string(&self.source).unwrap_or_default();
+        let mut document = contents
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|error| format!("{} is not valid toml: {}", self.source.display(), error))?;
+
+        let plugins = document
+            .entry("plugins")
+            .or_insert_with(|| {
+                let mut plugins = toml_edit::Table::new();
+                plugins.set_implicit(true);
+                toml_edit::Item::Table(plugins)
+            })
+            .as_table_mut()
+            .ok_or("`plugins` in fin.toml should be a table")?;
+        let plugin_table = plugins
+            .entry(plugin_name)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or(format!(
+                "`plugins.{}` in fin.toml should be a table",
+                plugin_name
+            ))?;
+
+        let new_item = match value {
+            // written as `[[plugins.<name>.<key>]]` sections rather than one long inline array
+            ConfigValue::Tables(tables) => {
+                toml_edit::Item::ArrayOfTables(tables.iter().map(Self::to_toml_table).collect())
+            }
+            _ => {
+                let mut new_value = Self::to_toml_value(value);
+                if let Some(existing_value) = plugin_table.get(key).and_then(|item| item.as_value())
+                {
+                    *new_value.decor_mut() = existing_value.decor().clone();
+                }
+                toml_edit::Item::Value(new_value)
+            }
+        };
+        plugin_table.insert(key, new_item);
+
+        Self::write_atomically(&target, &document.to_string())?;
+        self.source = target;
+        self.plugins
+            .entry(plugin_name.to_string())
+            .or_default()
+            .insert(key.to_string(), value.clone());
+        Ok(())
+    }
+    fn to_toml_value(value: &ConfigValue) -> toml_edit::Value {
+        match value {
+            ConfigValue::Bool(value) => toml_edit::Value::from(*value),
+            ConfigValue::Int(value) => toml_edit::Value::from(*value),
+            ConfigValue::String(value) => toml_edit::Value::from(value.as_str()),
+            ConfigValue::List(values) => {
+                toml_edit::Value::Array(values.iter().map(|value| value.as_str()).collect())
             }
+            ConfigValue::Tables(tables) => toml_edit::Value::Array(
+                tables
+                    .iter()
+                    .map(|table| Self::to_toml_table(table).into_inline_table())
+                    .collect(),
+            ),
         }
     }
-    fn load_config(&mut self, app: &tauri::App) {
-        self.set_source(app);
-        let config_file_contents = std::fs::read_to_string(&self.source)
-            .expect(&format!("{} does not exist", self.source.to_str().unwrap()));
-        let parsed_config: std::collections::HashMap<String, String> =
-            toml::from_str(&config_file_contents).expect("Should be valid toml");
-        self.configs = parsed_config;
-        return;
+    fn to_toml_table(config: &plugin_api::config::Config) -> toml_edit::Table {
+        let mut keys: Vec<&String> = config.keys().collect();
+        // HashMap order would reshuffle the file on every save
+        keys.sort();
+        let mut table = toml_edit::Table::new();
+        for key in keys {
+            table.insert(
+                key,
+                toml_edit::Item::Value(Self::to_toml_value(&config[key])),
+            );
+        }
+        table
     }
+    /// Writes to a temporary file next to the target and renames it over the target, so a
+    /// crash mid-write never leaves a truncated config behind
+    fn write_atomically(target: &std::path::Path, contents: &str) -> Result<(), String> {
+        let parent = target.parent().ok_or("Config path should have a parent")?;
+        std::fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+        let temporary = target.with_extension("toml.tmp");
+        std::fs::write(&temporary, contents).map_err(|error| error.to_string())?;
+        std::fs::rename(&temporary, target).map_err(|error| error.to_string())
+    }
+}
+
+/// Calls `on_change` whenever the user's fin.toml is created, modified, replaced or deleted
+pub fn watch(on_change: impl Fn() + Send + 'static) {
+    let Some(path) = Config::user_config_path() else {
+        return;
+    };
+    let Some(directory) = path.parent().map(|directory| directory.to_path_buf()) else {
+        return;
+    };
+    std::thread::spawn(move || {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                println!("Unable to watch {}: {}", path.display(), error);
+                return;
+            }
+        };
+        // the directory is watched rather than the file, since saving atomically replaces it
+        let watching = std::fs::create_dir_all(&directory)
+            .map_err(notify::Error::io)
+            .and_then(|()| watcher.watch(&directory, notify::RecursiveMode::NonRecursive));
+        if let Err(error) = watching {
+            println!("Unable to watch {}: {}", path.display(), error);
+            return;
+        }
+
+        while let Ok(event) = receiver.recv() {
+            let changed = match event {
+                Ok(event) => !event.kind.is_access() && event.paths.contains(&path),
+                Err(_) => false,
+            };
+            if !changed {
+                continue;
+            }
+            // editors often save in several steps, wait for them to settle before reloading
+            std::thread::sleep(Duration::from_millis(100));
+            while receiver.try_recv().is_ok() {}
+            on_change();
+        }
+    });
 }
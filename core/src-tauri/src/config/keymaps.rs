@@ -1,46 +1,257 @@
-use tauri::Manager;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tauri::{Emitter, Manager};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
-// pub struct KeyBinding {
-//     description: String,
-//     modifiers: Modifiers,
-//     code: Code,
-// }
-// pub struct KeyBindings {
-//
-// }
-
-pub fn init(app: &tauri::App) {
-    let global_shortcut = Shortcut::new(Some(Modifiers::ALT.union(Modifiers::SHIFT)), Code::Space);
+
+/// What a global shortcut does when pressed
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyAction {
+    /// Shows the launcher, or hides it when it's already focused
+    Toggle,
+    OpenSettings,
+    /// Shows the launcher with the query pre-filled, e.g. with a plugin's prefix
+    Prefill(String),
+}
+
+impl FromStr for KeyAction {
+    type Err = String;
+
+    fn from_str(action: &str) -> Result<Self, Self::Err> {
+        match action {
+            "toggle" => Ok(Self::Toggle),
+            "settings" => Ok(Self::OpenSettings),
+            _ => match action.strip_prefix("prefill:") {
+                Some(query) => Ok(Self::Prefill(query.to_string())),
+                None => Err(format!(
+                    "unknown action `{}`, expected `toggle`, `settings` or `prefill:<query>`",
+                    action
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyBinding {
+    description: String,
+    modifiers: Modifiers,
+    code: Code,
+    action: KeyAction,
+}
+
+impl KeyBinding {
+    /// Parses a binding such as `"ctrl+alt+space" = "toggle"`
+    pub fn parse(keys: &str, action: &str) -> Result<Self, String> {
+        let shortcut =
+            Shortcut::from_str(keys).map_err(|error| format!("`{}`: {}", keys, error))?;
+        let action = action
+            .parse()
+            .map_err(|error| format!("`{}`: {}", keys, error))?;
+        Ok(Self {
+            description: keys.to_string(),
+            modifiers: shortcut.mods,
+            code: shortcut.key,
+            action,
+        })
+    }
+    fn shortcut(&self) -> Shortcut {
+        Shortcut::new(Some(self.modifiers), self.code)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KeyBindings {
+    bindings: Vec<KeyBinding>,
+}
+
+impl KeyBindings {
+    /// Parses the `[keymaps]` table, returning an error for every binding that was skipped
+    pub fn parse(keymaps: &HashMap<String, String>) -> (Self, Vec<String>) {
+        let mut keymaps: Vec<(&String, &String)> = keymaps.iter().collect();
+        // keeps conflict reports stable between reloads
+        keymaps.sort();
+
+        let mut bindings: Vec<KeyBinding> = vec![];
+        let mut errors = vec![];
+        for (keys, action) in keymaps {
+            match KeyBinding::parse(keys, action) {
+                Ok(binding) => {
+                    match bindings
+                        .iter()
+                        .find(|existing| existing.shortcut() == binding.shortcut())
+                    {
+                        Some(existing) => errors.push(format!(
+                            "`{}` conflicts with `{}`",
+                            keys, existing.description
+                        )),
+                        None => bindings.push(binding),
+                    }
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+        (Self { bindings }, errors)
+    }
+    fn find(&self, shortcut: &Shortcut) -> Option<&KeyBinding> {
+        self.bindings
+            .iter()
+            .find(|binding| &binding.shortcut() == shortcut)
+    }
+}
+
+pub fn default_keymaps() -> HashMap<String, String> {
+    HashMap::from([("shift+alt+space".to_string(), "toggle".to_string())])
+}
+
+pub fn init(app: &tauri::App, keymaps: &HashMap<String, String>) -> Vec<String> {
+    app.manage(Arc::new(Mutex::new(KeyBindings::default())));
     app.handle()
         .plugin(
             tauri_plugin_global_shortcut::Builder::new()
                 .with_handler(move |app, shortcut, event| {
-                    if shortcut == &global_shortcut && event.state == ShortcutState::Pressed {
-                        let main_window = match app.get_webview_window("main") {
-                            Some(main_window) => main_window,
-                            None => {
-                                let main_window = tauri::WebviewWindowBuilder::from_config(
-                                    app,
-                                    app.config().app.windows.get(0).unwrap(),
-                                )
-                                .unwrap()
-                                .build()
-                                .unwrap();
-                                let _ = main_window.hide();
-                                main_window
-                            }
-                        };
-
-                        if main_window.is_focused().unwrap() {
-                            let _ = main_window.hide();
-                        } else {
-                            let _ = main_window.show();
-                            let _ = main_window.set_focus();
-                        }
+                    if event.state != ShortcutState::Pressed {
+                        return;
+                    }
+                    let action = app
+                        .state::<Arc<Mutex<KeyBindings>>>()
+                        .lock()
+                        .expect("Thread should not be poisoned")
+                        .find(shortcut)
+                        .map(|binding| binding.action.clone());
+                    if let Some(action) = action {
+                        run_action(app, action);
                     }
                 })
                 .build(),
         )
         .unwrap();
-    app.global_shortcut().register(global_shortcut).unwrap();
+    apply(app.handle(), keymaps)
+}
+
+/// Replaces the registered global shortcuts with the given keymaps. Registering waits for the
+/// main thread, so this must not be called while holding a lock the main thread might wait on.
+pub fn apply(app_handle: &tauri::AppHandle, keymaps: &HashMap<String, String>) -> Vec<String> {
+    let (mut key_bindings, mut errors) = KeyBindings::parse(keymaps);
+    let global_shortcut = app_handle.global_shortcut();
+    if let Err(error) = global_shortcut.unregister_all() {
+        errors.push(format!("Unable to unregister shortcuts: {}", error));
+    }
+    key_bindings.bindings.retain(
+        |binding| match global_shortcut.register(binding.shortcut()) {
+            Ok(()) => true,
+            Err(error) => {
+                errors.push(format!(
+                    "`{}` could not be registered: {}",
+                    binding.description, error
+                ));
+                false
+            }
+        },
+    );
+    for error in errors.iter() {
+        println!("Keymap error: {}", error);
+    }
+
+    *app_handle
+        .state::<Arc<Mutex<KeyBindings>>>()
+        .lock()
+        .expect("Thread should not be poisoned") = key_bindings;
+    errors
+}
+
+fn main_window(app: &tauri::AppHandle) -> tauri::WebviewWindow {
+    match app.get_webview_window("main") {
+        Some(main_window) => main_window,
+        None => {
+            let main_window = tauri::WebviewWindowBuilder::from_config(
+                app,
+                app.config().app.windows.first().unwrap(),
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+            let _ = main_window.hide();
+            main_window
+        }
+    }
+}
+
+fn run_action(app: &tauri::AppHandle, action: KeyAction) {
+    let main_window = main_window(app);
+    match action {
+        KeyAction::Toggle => {
+            if main_window.is_focused().unwrap() {
+                let _ = main_window.hide();
+                return;
+            }
+        }
+        KeyAction::OpenSettings => {
+            let _ = app.emit("open-settings", ());
+        }
+        KeyAction::Prefill(query) => {
+            let _ = app.emit("prefill-query", query);
+        }
+    }
+    let _ = main_window.show();
+    let _ = main_window.set_focus();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(keymaps: &[(&str, &str)]) -> (KeyBindings, Vec<String>) {
+        KeyBindings::parse(
+            &keymaps
+                .iter()
+                .map(|(keys, action)| (keys.to_string(), action.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!("toggle".parse(), Ok(KeyAction::Toggle));
+        assert_eq!("settings".parse(), Ok(KeyAction::OpenSettings));
+        assert_eq!(
+            "prefill:fs ".parse(),
+            Ok(KeyAction::Prefill("fs ".to_string()))
+        );
+        assert!("launch".parse::<KeyAction>().is_err());
+    }
+
+    #[test]
+    fn reports_conflicting_shortcuts() {
+        let (bindings, errors) = parse(&[
+            ("ctrl+alt+k", "toggle"),
+            ("alt+ctrl+k", "settings"),
+            ("control+alt+k", "prefill:x"),
+        ]);
+        assert_eq!(bindings.bindings.len(), 1);
+        assert_eq!(
+            errors,
+            [
+                "`control+alt+k` conflicts with `alt+ctrl+k`",
+                "`ctrl+alt+k` conflicts with `alt+ctrl+k`",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_invalid_bindings() {
+        let (bindings, errors) = parse(&[
+            ("ctrl+nokey", "toggle"),
+            ("ctrl+k", "launch"),
+            ("shift+alt+space", "toggle"),
+        ]);
+        assert_eq!(bindings.bindings.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("`ctrl+k`: unknown action `launch`"));
+        assert!(errors[1].starts_with("`ctrl+nokey`: "));
+        let shortcut = Shortcut::new(Some(Modifiers::SHIFT | Modifiers::ALT), Code::Space);
+        assert_eq!(bindings.find(&shortcut).unwrap().action, KeyAction::Toggle);
+    }
 }
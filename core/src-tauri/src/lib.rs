@@ -2,42 +2,94 @@ mod config;
 
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    sync::{PoisonError, RwLock},
 };
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 fn setup(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
-    app.manage(Arc::new(Mutex::new(config::Config::default())));
-    app.manage(Arc::new(Mutex::new(plugin_api::PluginManager::default())));
-
-    let plugin_manager_state = app.state::<Arc<Mutex<plugin_api::PluginManager>>>();
-
-    loop {
-        let plugin_manager_guard = plugin_manager_state.try_lock();
-        if plugin_manager_guard.is_ok() {
-            let mut plugin_manager = plugin_manager_guard.expect("Thread should not be poisoned");
-            plugin_manager.init(vec![
-                Box::new(core_plugin_calculator::CalculatorPlugin::default()),
-                Box::new(core_plugin_fs::FsPlugin::default()),
-            ]);
-            break;
+    let mut config = config::Config::default();
+    config.init(app);
+    let plugin_configs = config.get_plugin_configs();
+    app.manage(RwLock::new(config));
+
+    // plugins share the runtime Tauri already runs on
+    let mut plugin_manager =
+        plugin_api::PluginManager::with_runtime(tauri::async_runtime::handle().inner().clone());
+    let mut plugins: Vec<Box<dyn plugin_api::Plugin>> = vec![
+        Box::new(core_plugin_calculator::CalculatorPlugin::default()),
+        Box::new(core_plugin_fs::FsPlugin::default()),
+        Box::new(core_plugin_applications::ApplicationsPlugin::default()),
+    ];
+    if let Some(plugin_directory) = plugin_api::loader::default_plugin_directory() {
+        for plugin in plugin_manager.load_third_party_plugins(&plugin_directory) {
+            match plugin {
+                Ok(plugin) => plugins.push(plugin),
+                Err(error) => println!("Skipping third party plugin: {}", error),
+            }
         }
     }
+    plugin_manager.init(plugins, &plugin_configs);
+    forward_search_results(app.handle().clone(), plugin_manager.subscribe());
+    app.manage(plugin_manager);
+    app.manage(plugin_api::executor::ActionExecutor::default());
+
+    let app_handle = app.handle().clone();
+    config::watch(move || reload_config(&app_handle));
 
     #[cfg(target_os = "macos")]
     app.set_activation_policy(tauri::ActivationPolicy::Accessory);
 
-    let config_state = app.state::<Arc<Mutex<config::Config>>>();
-    loop {
-        let config_guard = config_state.try_lock();
-        if config_guard.is_ok() {
-            let mut config = config_guard.expect("Thread should not be poisoned");
-            config.init(app);
-            break;
+    Ok(())
+}
+
+fn forward_search_results(
+    app_handle: tauri::AppHandle,
+    receiver: std::sync::mpsc::Receiver<plugin_api::SearchResults>,
+) {
+    std::thread::spawn(move || {
+        for search_results in receiver {
+            if let Err(error) = app_handle.emit("search-results", search_results) {
+                println!("Failed to emit search results: {}", error);
+            }
         }
+    });
+}
+
+/// Re-reads fin.toml and applies it to the running plugins, reporting parse errors to the
+/// frontend instead of replacing the working config
+fn reload_config(app_handle: &tauri::AppHandle) {
+    let config_state = app_handle.state::<RwLock<config::Config>>();
+    let (previous_keymaps, keymaps, plugin_configs) = {
+        let mut config = config_state.write().unwrap_or_else(PoisonError::into_inner);
+        let previous_keymaps = config.get_keymaps();
+        if let Err(error) = config.load_config(app_handle) {
+            println!("Failed to reload config: {}", error);
+            let _ = app_handle.emit("config-error", error);
+            return;
+        }
+        (
+            previous_keymaps,
+            config.get_keymaps(),
+            config.get_plugin_configs(),
+        )
+    };
+
+    if keymaps != previous_keymaps {
+        let keymap_errors = config::keymaps::apply(app_handle, &keymaps);
+        config_state
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_keymap_errors(keymap_errors);
     }
+    let _ = app_handle.emit(
+        "config-changed",
+        &*config_state.read().unwrap_or_else(PoisonError::into_inner),
+    );
 
-    Ok(())
+    app_handle
+        .state::<plugin_api::PluginManager>()
+        .apply_plugin_configs(&plugin_configs);
+    println!("Config reloaded");
 }
 
 fn handle_window_events(window: &tauri::Window, event: &tauri::WindowEvent) {
@@ -65,7 +117,12 @@ pub fn run() {
             update_search_query,
             get_search_results,
             get_plugins,
-            update_plugin_config
+            update_plugin_config,
+            enable_plugin,
+            disable_plugin,
+            reload_plugin,
+            get_keymap_errors,
+            execute_action
         ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
@@ -73,74 +130,105 @@ pub fn run() {
 }
 
 #[tauri::command]
-fn update_search_query(app_handle: tauri::AppHandle, query: String) {
-    let plugin_manager_state = app_handle.state::<Arc<Mutex<plugin_api::PluginManager>>>();
-    loop {
-        let plugin_manager_guard = plugin_manager_state.try_lock();
-        if plugin_manager_guard.is_ok() {
-            let plugin_manager = plugin_manager_guard.expect("Thread should not be poisoned");
-            plugin_manager.get_client_state().update_search_query(query);
-            break;
-        }
-    }
+fn update_search_query(
+    plugin_manager: tauri::State<'_, plugin_api::PluginManager>,
+    query: String,
+) -> u64 {
+    plugin_manager.update_search_query(query)
 }
 
 #[tauri::command]
 fn get_search_results(
-    app_handle: tauri::AppHandle,
+    plugin_manager: tauri::State<'_, plugin_api::PluginManager>,
 ) -> Result<Vec<plugin_api::SearchResult>, String> {
-    let plugin_manager_state = app_handle.state::<Arc<Mutex<plugin_api::PluginManager>>>();
-    loop {
-        let plugin_manager_guard = plugin_manager_state.try_lock();
-        if plugin_manager_guard.is_ok() {
-            let plugin_manager = plugin_manager_guard.expect("Thread should not be poisoned");
-            let search_results = plugin_manager.get_client_state().get_search_results();
-            break Ok(search_results);
-        }
+    Ok(plugin_manager.get_client_state().get_search_results())
+}
+
+/// Runs an action of a picked result and hides the launcher, which stays open to show the error
+/// if the action failed. The webview only names the result, the action is looked up in the
+/// results the plugins returned.
+#[tauri::command]
+fn execute_action(
+    window: tauri::Window,
+    executor: tauri::State<'_, plugin_api::executor::ActionExecutor>,
+    plugin_manager: tauri::State<'_, plugin_api::PluginManager>,
+    query_id: u64,
+    result_index: usize,
+    action_index: usize,
+) -> Result<(), plugin_api::executor::ActionError> {
+    let action = plugin_manager.pick_action(query_id, result_index, action_index)?;
+    executor.execute(&action, &plugin_manager)?;
+    if let Err(error) = window.hide() {
+        println!("Failed to hide the window: {}", error);
     }
+    Ok(())
 }
 
 #[tauri::command]
 fn get_plugins(
-    app_handle: tauri::AppHandle,
+    plugin_manager: tauri::State<'_, plugin_api::PluginManager>,
 ) -> Result<HashMap<String, plugin_api::PluginData>, String> {
-    let plugin_manager_state = app_handle.state::<Arc<Mutex<plugin_api::PluginManager>>>();
-    loop {
-        let plugin_manager_guard = plugin_manager_state.try_lock();
-        if plugin_manager_guard.is_ok() {
-            let plugin_manager = plugin_manager_guard.expect("Thread should not be poisoned");
-            let plugins = plugin_manager.get_plugins();
-            break Ok(plugins);
-        }
-    }
+    Ok(plugin_manager.get_plugins())
 }
 
 #[tauri::command]
 fn update_plugin_config(
-    app_handle: tauri::AppHandle,
+    plugin_manager: tauri::State<'_, plugin_api::PluginManager>,
+    config: tauri::State<'_, RwLock<config::Config>>,
     plugin_name: String,
     key: String,
-    value: String,
-) {
-    let plugin_manager_state = app_handle.state::<Arc<Mutex<plugin_api::PluginManager>>>();
-    loop {
-        let plugin_manager_guard = plugin_manager_state.try_lock();
-        if plugin_manager_guard.is_ok() {
-            let mut plugin_manager = plugin_manager_guard.expect("Thread should not be poisoned");
-            let loaded_plugin_arc = plugin_manager
-                .get_plugin_mut(&plugin_name)
-                .expect("Plugin not found");
-            println!("Updating config for plugin {}", plugin_name);
-            let mut loaded_plugin = loaded_plugin_arc
-                .lock()
-                .expect("Thread should not be poisoned");
-            println!(
-                "Config updated for plugin {} with {}={}",
-                plugin_name, key, value
-            );
-
-            loaded_plugin.config.insert(key, value);
-            break;
-        }
-    }
+    value: plugin_api::config::ConfigValue,
+) -> Result<(), plugin_api::config::ConfigError> {
+    println!("Updating config for plugin {}", plugin_name);
+    plugin_manager.update_plugin_config(&plugin_name, &key, value.clone())?;
+    config
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .save_plugin_config(&plugin_name, &key, &value)
+        .map_err(|message| plugin_api::config::ConfigError::SaveFailed { message })
+}
+
+/// Runs a plugin lifecycle change off the main thread, since stopping a worker waits for the
+/// plugin to finish whatever it's doing
+async fn change_plugin(
+    app_handle: tauri::AppHandle,
+    change: impl FnOnce(&plugin_api::PluginManager) -> Result<(), String> + Send + 'static,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        change(&app_handle.state::<plugin_api::PluginManager>())
+    })
+    .await
+    .map_err(|error| error.to_string())?
+}
+
+#[tauri::command]
+async fn enable_plugin(app_handle: tauri::AppHandle, plugin_name: String) -> Result<(), String> {
+    change_plugin(app_handle, move |plugin_manager| {
+        plugin_manager.enable_plugin(&plugin_name)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn disable_plugin(app_handle: tauri::AppHandle, plugin_name: String) -> Result<(), String> {
+    change_plugin(app_handle, move |plugin_manager| {
+        plugin_manager.disable_plugin(&plugin_name)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn reload_plugin(app_handle: tauri::AppHandle, plugin_name: String) -> Result<(), String> {
+    change_plugin(app_handle, move |plugin_manager| {
+        plugin_manager.reload_plugin(&plugin_name)
+    })
+    .await
+}
+
+#[tauri::command]
+fn get_keymap_errors(config: tauri::State<'_, RwLock<config::Config>>) -> Vec<String> {
+    config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_keymap_errors()
 }
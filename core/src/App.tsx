@@ -1,11 +1,36 @@
 import "./App.css";
 import Settings from "./pages/Settings";
 import Search from "./pages/Search";
-import { Route, Routes } from "react-router";
-// import { useNavigate } from "react-router";
-// import { useEffect } from "react";
+import { Route, Routes, useNavigate } from "react-router";
+import { useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { getCurrentWindow, LogicalSize } from "@tauri-apps/api/window";
+import { useApp } from "./store/app";
+import { useSearchStore } from "./store/search";
 
 function App() {
+  const navigate = useNavigate();
+  const { setCurrentPage } = useApp();
+  const { setQuery } = useSearchStore();
+
+  // global shortcuts bound in the [keymaps] section of fin.toml
+  useEffect(() => {
+    const unlistenSettings = listen("open-settings", () => {
+      getCurrentWindow().setSize(new LogicalSize(600, 400));
+      navigate("/settings");
+      setCurrentPage("settings");
+    });
+    const unlistenPrefill = listen<string>("prefill-query", (event) => {
+      navigate("/");
+      setCurrentPage("search");
+      setQuery(event.payload);
+    });
+    return () => {
+      unlistenSettings.then((unlisten) => unlisten());
+      unlistenPrefill.then((unlisten) => unlisten());
+    };
+  }, []);
+
   // const navigate = useNavigate();
   // useEffect(() => {
   //   navigate("/settings");
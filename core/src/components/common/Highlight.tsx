@@ -0,0 +1,31 @@
+import { T_Range } from "../../store/search";
+
+// ranges count characters rather than UTF-16 code units, hence Array.from
+function Highlight({ text, ranges }: { text: string; ranges: T_Range[] }) {
+  const characters = Array.from(text);
+  const parts = [];
+  let position = 0;
+  for (const { start, end } of ranges) {
+    if (start > position) {
+      parts.push(
+        <span key={position}>
+          {characters.slice(position, start).join("")}
+        </span>,
+      );
+    }
+    parts.push(
+      <span key={start} className="text-white font-semibold">
+        {characters.slice(start, end).join("")}
+      </span>,
+    );
+    position = end;
+  }
+  if (position < characters.length) {
+    parts.push(
+      <span key={position}>{characters.slice(position).join("")}</span>,
+    );
+  }
+  return <>{parts}</>;
+}
+
+export default Highlight;
@@ -1,38 +1,108 @@
-import { SearchIcon } from "lucide-react";
+import { LoaderCircle, SearchIcon } from "lucide-react";
 import Icon from "../components/common/Icon";
+import Highlight from "../components/common/Highlight";
 import {
   useEffect,
   useRef,
   createRef,
   RefObject,
   MutableRefObject,
+  KeyboardEvent,
 } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getCurrentWindow, LogicalSize } from "@tauri-apps/api/window";
-import { Command } from "@tauri-apps/plugin-shell";
 import { useHotkeys, isHotkeyPressed } from "react-hotkeys-hook";
 import { useApp } from "../store/app";
 import { useNavigate } from "react-router";
-import { T_Result, useSearchStore } from "../store/search";
+import {
+  T_ActionError,
+  T_Result,
+  T_ResultAction,
+  T_SearchResults,
+  useSearchStore,
+} from "../store/search";
+
+function describeActionError(error: T_ActionError): string {
+  switch (error.kind) {
+    case "not_found":
+      return `${error.path} does not exist`;
+    case "empty_command":
+      return "The command to run is empty";
+    case "program_not_found":
+      return `${error.program} is not installed`;
+    case "spawn_failed":
+      return `Failed to run ${error.program}: ${error.message}`;
+    case "clipboard_failed":
+      return `Failed to copy: ${error.message}`;
+    case "invalid_url":
+      return `${error.url} is not a URL`;
+    case "plugin_not_running":
+      return `Plugin ${error.plugin} is not running`;
+    case "stale_result":
+      return "The results changed, pick the result again";
+    default:
+      return "Failed to run the action";
+  }
+}
+
+// identifies a result across snapshots of the same query
+function resultKey(result: T_Result): string {
+  return JSON.stringify([
+    result.plugin,
+    result.title,
+    result.actions[0]?.action,
+  ]);
+}
+
+function shortcutOf(action: T_ResultAction, index: number): string | null {
+  return action.shortcut ?? (index === 0 ? "Enter" : null);
+}
+
+// shortcuts are `+` separated modifiers followed by a key, e.g. "Ctrl+Shift+C"
+function matchesShortcut(event: KeyboardEvent, shortcut: string) {
+  const keys = shortcut.toLowerCase().split("+");
+  const key = keys.pop();
+  const modifiers = new Set(keys);
+  return (
+    event.ctrlKey === modifiers.has("ctrl") &&
+    event.altKey === modifiers.has("alt") &&
+    event.shiftKey === modifiers.has("shift") &&
+    event.metaKey === (modifiers.has("meta") || modifiers.has("cmd")) &&
+    // the code for letters, Alt changes the key they type on macOS
+    (event.key.toLowerCase() === key ||
+      event.code.toLowerCase() === `key${key}`)
+  );
+}
 
 function Search() {
   const inputRef = useRef<HTMLInputElement>(null);
   const {
     query,
+    queryId,
     results,
+    pendingPlugins,
     history,
     selected,
     selectedHistory,
+    actionError,
     setQuery,
     setResults,
+    setQueryId,
+    setSearchResults,
     setHistory,
     setSelected,
     setSelectedHistory,
+    setActionError,
   } = useSearchStore();
   const listItemRefs = useRef<RefObject<HTMLLIElement>[]>([]);
+  const previousResults = useRef<T_Result[]>([]);
   const currentWindow = getCurrentWindow();
   const { setCurrentPage } = useApp();
   const navigate = useNavigate();
+  // until every plugin the query was sent to has answered
+  const searching =
+    query.length > 0 && (pendingPlugins === null || pendingPlugins.length > 0);
 
   const clearSearchRef = useHotkeys(
     "escape, ctrl+[",
@@ -115,34 +185,29 @@ function Search() {
     },
   );
 
-  const actionRef = useHotkeys("enter", async () => {
-    const selectedResult = results![selected!]!;
-    if (selectedResult.action.LaunchApplication) {
-      Command.create("exec-sh", [
-        "-c",
-        `open ${selectedResult.action.LaunchApplication}`,
-      ])
-        .execute()
-        .then((result) => {
-          console.log(result);
-        });
-    } else if (selectedResult.action.Open) {
-      Command.create("exec-sh", [
-        "-c",
-        `open -R "${selectedResult.action.Open}"`,
-      ])
-        .execute()
-        .then((result) => {
-          console.log(result);
-        });
-    } else if (selectedResult.action.Copy) {
-      await navigator.clipboard.writeText(
-        selectedResult.title.toString() || "",
+  async function runAction(resultIndex: number, actionIndex: number) {
+    // the backend looks the action up and hides the window once it ran
+    await invoke("execute_action", { queryId, resultIndex, actionIndex })
+      .then(() => setActionError(null))
+      .catch((error: T_ActionError) =>
+        setActionError(describeActionError(error)),
       );
-    }
     setSelected(null);
     inputRef.current?.focus();
-  }) as unknown as MutableRefObject<HTMLLIElement>;
+  }
+
+  function runShortcut(event: KeyboardEvent, resultIndex: number) {
+    const actionIndex = results[resultIndex].actions.findIndex(
+      (action, index) => {
+        const shortcut = shortcutOf(action, index);
+        return shortcut !== null && matchesShortcut(event, shortcut);
+      },
+    );
+    if (actionIndex !== -1) {
+      event.preventDefault();
+      runAction(resultIndex, actionIndex);
+    }
+  }
 
   useHotkeys(
     "meta+comma",
@@ -158,11 +223,11 @@ function Search() {
   );
 
   async function updateResults() {
-    if (query.length > 0) {
-      await invoke("update_search_query", {
-        query,
-      });
-    } else setResults([]);
+    const queryId = await invoke<number>("update_search_query", {
+      query,
+    });
+    setQueryId(queryId);
+    if (query.length === 0) setResults([]);
   }
 
   useEffect(() => {
@@ -170,24 +235,14 @@ function Search() {
   }, [query]);
 
   useEffect(() => {
-    let interval: number;
-    if (query.length > 0) {
-      interval = setInterval(() => {
-        invoke("get_search_results").then((response) => {
-          if (JSON.stringify(response) !== JSON.stringify(results)) {
-            response = (response as T_Result[]).sort(
-              (a, b) => b.priority - a.priority,
-            );
-            setResults(response as T_Result[]);
-          }
-        });
-      }, 100);
-    }
+    const unlisten = listen<T_SearchResults>("search-results", (event) => {
+      setSearchResults(event.payload);
+    });
 
     return () => {
-      clearInterval(interval);
+      unlisten.then((unlisten) => unlisten());
     };
-  }, [query]);
+  }, []);
 
   useEffect(() => {
     clearSearchRef.current = inputRef.current!;
@@ -197,18 +252,26 @@ function Search() {
   useEffect(() => {
     if ((results.length > 0 && results.length > 0) || query) {
       currentWindow.setSize(new LogicalSize(600, 400));
-      listItemRefs.current = Array(results.length)
-        .fill(null)
-        .map(() => createRef<HTMLLIElement>());
-      setSelected(null);
+      listItemRefs.current = results.map(
+        (_, index) => listItemRefs.current[index] ?? createRef<HTMLLIElement>(),
+      );
+      // results of slower plugins are ranked in, keep the selection on the same result
+      const selectedResult =
+        selected === null ? undefined : previousResults.current[selected];
+      const index = selectedResult
+        ? results.findIndex(
+            (result) => resultKey(result) === resultKey(selectedResult),
+          )
+        : -1;
+      setSelected(index === -1 ? null : index);
     } else {
       currentWindow.setSize(new LogicalSize(600, 50));
     }
+    previousResults.current = results;
   }, [query, results]);
   useEffect(() => {
     if (selected !== null) {
       selectedListItemRef.current = listItemRefs.current[selected].current!;
-      actionRef.current = selectedListItemRef.current;
       const listItem = listItemRefs.current[selected];
       listItem?.current?.focus();
       listItem?.current?.scrollIntoView({
@@ -229,7 +292,11 @@ function Search() {
         data-tauri-drag-region
         className="w-full px-3 py-2 flex flex-row items-center gap-1 rounded-md bg-zinc-800 text-white"
       >
-        <SearchIcon />
+        {searching ? (
+          <LoaderCircle className="animate-spin" />
+        ) : (
+          <SearchIcon />
+        )}
         <input
           ref={inputRef}
           tabIndex={0}
@@ -244,6 +311,14 @@ function Search() {
           className="w-full px-1 rounded-md text-white text-lg bg-transparent outline-none focus:outline-none"
         />
       </div>
+      {actionError && (
+        <p className="w-full px-3 py-1 truncate text-xs text-red-400">
+          {actionError}
+        </p>
+      )}
+      {!searching && query.length > 0 && results.length === 0 && (
+        <p className="w-full px-3 py-2 text-sm text-neutral-500">No results</p>
+      )}
       {results.length > 0 && (
         <ul
           tabIndex={1}
@@ -259,11 +334,23 @@ function Search() {
                 setHistory([...history, query]);
                 setSelected(index);
               }}
+              onKeyDown={(e) => index === selected && runShortcut(e, index)}
             >
               <div className="w-full overflow-hidden">
                 <p className="w-full truncate text-neutral-400">
-                  {item.title.toString()}
+                  <Highlight
+                    text={item.title.toString()}
+                    ranges={item.title_matches}
+                  />
                 </p>
+                {item.description && (
+                  <p className="w-full truncate text-xs text-neutral-500">
+                    <Highlight
+                      text={item.description}
+                      ranges={item.description_matches}
+                    />
+                  </p>
+                )}
               </div>
               <span className="rounded-lg p-1 flex flex-row items-center justify-center hover:bg-white/10 hover:cursor-pointer">
                 {<Icon icon={item.icon} size={20} />}
@@ -272,6 +359,30 @@ function Search() {
           ))}
         </ul>
       )}
+      {selected !== null && results[selected]?.actions.length > 0 && (
+        <div className="w-full px-2 py-1 flex flex-row justify-end items-center gap-3 text-xs text-neutral-400 border-t border-white/10">
+          {results[selected].actions.map((action, index) => {
+            const shortcut = shortcutOf(action, index);
+            return (
+              <button
+                key={index}
+                tabIndex={-1}
+                className="px-1 flex flex-row items-center gap-1 rounded-md hover:bg-white/10"
+                // keeps the selected result focused
+                onMouseDown={(e) => e.preventDefault()}
+                onClick={() => runAction(selected, index)}
+              >
+                <span>{action.label}</span>
+                {shortcut && (
+                  <kbd className="px-1 rounded bg-white/10 text-neutral-300">
+                    {shortcut}
+                  </kbd>
+                )}
+              </button>
+            );
+          })}
+        </div>
+      )}
     </>
   );
 }
@@ -2,17 +2,26 @@ import { useHotkeys } from "react-hotkeys-hook";
 import { getCurrentWindow, LogicalSize } from "@tauri-apps/api/window";
 import { useApp } from "../store/app";
 import { useNavigate } from "react-router";
-import { SearchIcon } from "lucide-react";
+import { Plus, RotateCw, SearchIcon, X } from "lucide-react";
 import Icon from "../components/common/Icon";
-import { useSettingsStore } from "../store/settings";
-import { useEffect } from "react";
-import { invoke } from "@tauri-apps/api/core";
+import {
+  T_ConfigError,
+  T_ConfigField,
+  T_ConfigValue,
+  T_PluginStatus,
+  useSettingsStore,
+} from "../store/settings";
+import { useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
 
 function PluginList() {
   const { plugins, refreshPlugins, setSelectedPlugin, selectedPlugin } =
     useSettingsStore();
   useEffect(() => {
     refreshPlugins();
+    // plugin status changes in the background, e.g. when a crashed plugin restarts
+    const interval = setInterval(refreshPlugins, 2000);
+    return () => clearInterval(interval);
   }, []);
   return (
     <ul className="w-full h-full flex flex-col justify-start items-center gap-1 overflow-y-scroll overflow-x-hidden no-scrollbar outline-none focus:outline-none">
@@ -21,53 +30,293 @@ function PluginList() {
         .map((plugin, index) => (
           <li
             key={index}
-            className={`w-full h-7 p-2 flex flex-row justify-start items-center gap-4 text-white rounded-lg outline-none focus:outline-none hover:cursor-pointer ${selectedPlugin === plugin.metadata.name && "bg-white/10"}`}
+            className={`w-full h-7 p-2 flex flex-row justify-start items-center gap-4 text-white rounded-lg outline-none focus:outline-none hover:cursor-pointer ${selectedPlugin === plugin.metadata.name && "bg-white/10"} ${!plugin.enabled && "opacity-50"}`}
             onClick={() => setSelectedPlugin(plugin.metadata.name)}
           >
             <Icon icon={plugin.metadata.icon} size={20} />
             <span>{plugin.metadata.name}</span>
+            {plugin.status.state !== "healthy" && (
+              <span
+                title={plugin.status.message}
+                className={`ml-auto w-2 h-2 rounded-full ${plugin.status.state === "degraded" ? "bg-yellow-500" : "bg-red-500"}`}
+              />
+            )}
           </li>
         ))}
     </ul>
   );
 }
 
+function PluginControls() {
+  const { getSelectedPlugin, setPluginEnabled, reloadPlugin } =
+    useSettingsStore();
+  const plugin = getSelectedPlugin();
+  if (!plugin) return null;
+  return (
+    <>
+      <div className="w-full h-7 px-2 flex flex-row justify-between items-center gap-4 text-white">
+        <span>{plugin.metadata.name}</span>
+        <div className="flex flex-row items-center gap-2">
+          <label className="flex flex-row items-center gap-1 hover:cursor-pointer">
+            <input
+              type="checkbox"
+              checked={plugin.enabled}
+              onChange={(e) =>
+                setPluginEnabled(plugin.metadata.name, e.currentTarget.checked)
+              }
+            />
+            <span>Enabled</span>
+          </label>
+          <span
+            title="Reload"
+            className="rounded-lg p-1 flex flex-row items-center justify-center hover:bg-white/10 hover:cursor-pointer"
+            onClick={() => reloadPlugin(plugin.metadata.name)}
+          >
+            <RotateCw size={16} />
+          </span>
+        </div>
+      </div>
+      {plugin.status.state !== "healthy" && (
+        <div
+          className={`w-full px-2 py-1 rounded-md text-sm ${plugin.status.state === "degraded" ? "bg-yellow-500/20 text-yellow-200" : "bg-red-500/20 text-red-300"}`}
+        >
+          {describePluginStatus(plugin.status)}
+        </div>
+      )}
+      {plugin.crashes > 0 && (
+        <div className="w-full px-2 text-xs text-white/50">
+          Crashed {plugin.crashes} {plugin.crashes === 1 ? "time" : "times"}{" "}
+          since fin started
+        </div>
+      )}
+    </>
+  );
+}
+
+function describePluginStatus(status: T_PluginStatus): string {
+  switch (status.state) {
+    case "healthy":
+      return "Running";
+    case "degraded":
+      return `Degraded: ${status.message}`;
+    case "failed":
+      return `Failed to start: ${status.message}`;
+    case "crashed":
+      return `Crashed: ${status.message}, restarting in ${status.restart_in}s`;
+  }
+}
+
+function describeConfigError(error: T_ConfigError): string {
+  switch (error.kind) {
+    case "unknown_plugin":
+      return `Plugin ${error.plugin} not found`;
+    case "unknown_key":
+      return `Unknown config key ${error.key}`;
+    case "unknown_field":
+      return `${error.key} is not a known field`;
+    case "invalid_type":
+      return `Expected a value of type ${error.expected}`;
+    case "out_of_range":
+      return `Should be between ${error.min ?? "-∞"} and ${error.max ?? "∞"}`;
+    case "invalid_option":
+      return `Should be one of ${error.options?.join(", ")}`;
+    case "path_not_found":
+      return `${error.path} does not exist`;
+    case "not_a_directory":
+      return `${error.path} is not a directory`;
+    case "save_failed":
+      return `Failed to save: ${error.message}`;
+    default:
+      return "Invalid value";
+  }
+}
+
+function ConfigInput({
+  field,
+  value,
+  onChange,
+}: {
+  field: T_ConfigField;
+  value: T_ConfigValue;
+  onChange: (value: T_ConfigValue) => void;
+}) {
+  const className =
+    "w-full px-2 py-[0.1rem] rounded-md bg-white/5 outline-none focus:outline-none";
+  switch (field.type) {
+    case "bool":
+      return (
+        <input
+          type="checkbox"
+          checked={value as boolean}
+          onChange={(e) => onChange(e.currentTarget.checked)}
+        />
+      );
+    case "int":
+      return (
+        <input
+          type="number"
+          defaultValue={value as number}
+          min={field.min ?? undefined}
+          max={field.max ?? undefined}
+          className={className}
+          onBlur={(e) => onChange(parseInt(e.currentTarget.value, 10))}
+        />
+      );
+    case "enum":
+      return (
+        <select
+          value={value as string}
+          className={className}
+          onChange={(e) => onChange(e.currentTarget.value)}
+        >
+          {field.options?.map((option) => (
+            <option key={option} value={option}>
+              {option}
+            </option>
+          ))}
+        </select>
+      );
+    case "string_list":
+      return (
+        <input
+          type="text"
+          defaultValue={(value as string[]).join(", ")}
+          className={className}
+          onBlur={(e) =>
+            onChange(
+              e.currentTarget.value
+                .split(",")
+                .map((item) => item.trim())
+                .filter((item) => item.length > 0),
+            )
+          }
+        />
+      );
+    case "table_list":
+      return (
+        <TableListInput field={field} value={value} onChange={onChange} />
+      );
+    default:
+      return (
+        <input
+          type="text"
+          defaultValue={value as string}
+          className={className}
+          onBlur={(e) => onChange(e.currentTarget.value)}
+        />
+      );
+  }
+}
+
+function TableListInput({
+  field,
+  value,
+  onChange,
+}: {
+  field: T_ConfigField;
+  value: T_ConfigValue;
+  onChange: (value: T_ConfigValue) => void;
+}) {
+  const tables = value as Record<string, T_ConfigValue>[];
+  const fields = field.fields ?? [];
+  const defaults = () =>
+    Object.fromEntries(fields.map((field) => [field.key, field.default]));
+  return (
+    <div className="w-full flex flex-col justify-start items-start gap-1">
+      {tables.map((table, index) => (
+        // inputs keep their own value, remount them when a table is removed
+        <div
+          key={`${tables.length}-${index}`}
+          className="w-full p-1 flex flex-row justify-start items-start gap-2 rounded-md border border-white/10"
+        >
+          <div className="w-full flex flex-col justify-start items-start gap-1">
+            {fields.map((subfield) => (
+              <div
+                key={subfield.key}
+                title={subfield.description}
+                className="w-full h-7 flex flex-row justify-start items-center gap-4"
+              >
+                <span className="w-32 flex-shrink-0">{subfield.key}</span>
+                <ConfigInput
+                  field={subfield}
+                  value={table[subfield.key] ?? subfield.default}
+                  onChange={(value) =>
+                    onChange(
+                      tables.map((table, i) =>
+                        i === index
+                          ? { ...table, [subfield.key]: value }
+                          : table,
+                      ),
+                    )
+                  }
+                />
+              </div>
+            ))}
+          </div>
+          <span
+            title="Remove"
+            className="rounded-lg p-1 flex flex-row items-center justify-center hover:bg-white/10 hover:cursor-pointer"
+            onClick={() => onChange(tables.filter((_, i) => i !== index))}
+          >
+            <X size={16} />
+          </span>
+        </div>
+      ))}
+      <span
+        title="Add"
+        className="rounded-lg p-1 flex flex-row items-center justify-center hover:bg-white/10 hover:cursor-pointer"
+        onClick={() => onChange([...tables, defaults()])}
+      >
+        <Plus size={16} />
+      </span>
+    </div>
+  );
+}
+
 function PluginConfig() {
-  const { updatePlugin, getSelectedPlugin } = useSettingsStore();
+  const { getSelectedPlugin, updatePluginConfig } = useSettingsStore();
+  const [errors, setErrors] = useState<Record<string, string>>({});
+  const plugin = getSelectedPlugin();
+  useEffect(() => {
+    setErrors({});
+  }, [plugin?.metadata.name]);
   return (
     <>
-      {getSelectedPlugin() ? (
-        Object.keys(getSelectedPlugin()!.config).length > 0 ? (
+      {plugin ? (
+        plugin.schema.length > 0 ? (
           <ul className="w-full p-1 flex flex-col justify-start items-center gap-1 overflow-y-scroll overflow-x-hidden no-scrollbar outline-none focus:outline-none border border-white/20 rounded-lg">
-            {Object.entries(getSelectedPlugin()?.config ?? {}).map(
-              (config, index) => (
-                <li
-                  key={index}
-                  className="w-full h-7 px-2 flex flex-row justify-start items-center gap-4 text-white rounded-xl outline-none focus:outline-none"
-                >
-                  <span>{config[0]}</span>
-                  <input
-                    type="text"
-                    placeholder={config[1]}
-                    className="w-full px-2 py-[0.1rem] rounded-md bg-white/5 outline-none focus:outline-none"
-                    onBlur={(e) => {
-                      updatePlugin({
-                        ...getSelectedPlugin()!,
-                        config: {
-                          ...getSelectedPlugin()!.config,
-                          [config[0]]: e.currentTarget.value,
-                        },
-                      });
-                      invoke("update_plugin_config", {
-                        pluginName: getSelectedPlugin()!.metadata.name,
-                        key: config[0],
-                        value: e.currentTarget.value,
-                      });
+            {plugin.schema.map((field) => (
+              <li
+                key={`${plugin.metadata.name}-${field.key}`}
+                title={field.description}
+                className="w-full px-2 flex flex-col justify-start items-start text-white rounded-xl outline-none focus:outline-none"
+              >
+                <div className="w-full min-h-7 flex flex-row justify-start items-center gap-4">
+                  <span>{field.key}</span>
+                  <ConfigInput
+                    field={field}
+                    value={plugin.config[field.key] ?? field.default}
+                    onChange={(value) => {
+                      updatePluginConfig(
+                        plugin.metadata.name,
+                        field.key,
+                        value,
+                      ).then((error) =>
+                        setErrors((errors) => ({
+                          ...errors,
+                          [field.key]: error ? describeConfigError(error) : "",
+                        })),
+                      );
                     }}
                   />
-                </li>
-              ),
-            )}
+                </div>
+                {errors[field.key] && (
+                  <span className="text-xs text-red-400">
+                    {errors[field.key]}
+                  </span>
+                )}
+              </li>
+            ))}
           </ul>
         ) : (
           <div className="w-full h-full flex flex-col justify-center items-center gap-1 text-white">
@@ -86,8 +335,32 @@ function PluginConfig() {
 function Settings() {
   const currentWindow = getCurrentWindow();
   const { setCurrentPage } = useApp();
+  const {
+    configError,
+    keymapErrors,
+    setConfigError,
+    refreshPlugins,
+    refreshKeymapErrors,
+  } = useSettingsStore();
   const navigate = useNavigate();
 
+  // fin.toml was edited on disk
+  useEffect(() => {
+    refreshKeymapErrors();
+    const unlistenChanged = listen("config-changed", () => {
+      setConfigError(null);
+      refreshPlugins();
+      refreshKeymapErrors();
+    });
+    const unlistenError = listen<string>("config-error", (event) => {
+      setConfigError(event.payload);
+    });
+    return () => {
+      unlistenChanged.then((unlisten) => unlisten());
+      unlistenError.then((unlisten) => unlisten());
+    };
+  }, []);
+
   useHotkeys("escape, ctrl+[", () => {
     currentWindow.setSize(new LogicalSize(600, 50));
     navigate("/");
@@ -102,6 +375,19 @@ function Settings() {
       <div className="h-3 flex flex-row items-center gap-1 rounded-md bg-zinc-800 text-white">
         <span className="text-white">Settings</span>
       </div>
+      {configError && (
+        <div className="w-full px-2 py-1 rounded-md bg-red-500/20 text-sm text-red-300">
+          fin.toml could not be loaded: {configError}
+        </div>
+      )}
+      {keymapErrors.map((keymapError, index) => (
+        <div
+          key={index}
+          className="w-full px-2 py-1 rounded-md bg-yellow-500/20 text-sm text-yellow-200"
+        >
+          Keymap skipped: {keymapError}
+        </div>
+      ))}
       <div className="w-full h-full px-1 py-2 flex justify-evenly items-center gap-1 overflow-y-scroll overflow-x-hidden no-scrollbar outline-none focus:outline-none">
         {/* plugin list and picker */}
         <div className="w-48 h-full pr-3 flex flex-col flex-shrink-0 flex-grow-0 justify-start items-center gap-1 text-sm text-white border-r-[0.07rem] border-white/20">
@@ -126,6 +412,7 @@ function Settings() {
           */}
           {/* Settings editor */}
           <div className="w-full h-full flex flex-col justify-start items-center gap-1 overflow-y-scroll overflow-x-hidden no-scrollbar outline-none focus:outline-none">
+            <PluginControls />
             <PluginConfig />
           </div>
         </div>
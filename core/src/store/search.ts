@@ -1,40 +1,103 @@
 import { create } from "zustand";
 
+export type T_Range = {
+  start: number;
+  end: number;
+};
+
+export type T_ResultAction = {
+  label: string;
+  action: any;
+  // e.g. "Ctrl+Enter", the first action of a result runs on Enter without one
+  shortcut: string | null;
+};
+
 export type T_Result = {
   title: string;
   description: string | null;
   icon: string | null;
-  action: any;
+  // the first runs on Enter, all are listed in the action panel
+  actions: T_ResultAction[];
   priority: number;
+  score: number | null;
+  title_matches: T_Range[];
+  description_matches: T_Range[];
+  // the plugin that returned the result, handed back when it's picked
+  plugin: string | null;
+};
+
+export type T_ActionError = {
+  kind: string;
+  path?: string;
+  program?: string;
+  message?: string;
+  url?: string;
+  plugin?: string;
+};
+
+// merged and ranked by the backend, replaces the previous snapshot for the same query
+export type T_SearchResults = {
+  query_id: number;
+  results: T_Result[];
+  // plugins that haven't answered yet, the results are complete once it's empty
+  pending: string[];
 };
 
 export type T_SearchStore = {
   query: string;
+  queryId: number | null;
   results: T_Result[];
+  // null until the first results of the query arrive
+  pendingPlugins: string[] | null;
   history: string[];
   selected: number | null;
   selectedHistory: number | null;
+  // why the action of the picked result failed, until the query changes
+  actionError: string | null;
   setQuery: (query: string) => void;
   setResults: (results: T_Result[]) => void;
+  setQueryId: (queryId: number) => void;
+  setSearchResults: (searchResults: T_SearchResults) => void;
   setHistory: (history: string[]) => void;
   setSelected: (selected: number | null) => void;
   setSelectedHistory: (selectedHistory: number | null) => void;
+  setActionError: (actionError: string | null) => void;
 };
 
 export const useSearchStore = create<T_SearchStore>((set, get) => ({
   query: "",
+  queryId: null,
   results: [],
+  pendingPlugins: null,
   history: [],
   selected: null,
   selectedHistory: null,
-  setQuery: (query: string) => set({ query }),
+  actionError: null,
+  setQuery: (query: string) => set({ query, actionError: null }),
   setResults: (results: T_Result[]) => {
     if (JSON.stringify(results) !== JSON.stringify(get().results)) {
       set({ results });
     }
   },
+  setQueryId: (queryId: number) => {
+    const current = get().queryId;
+    if (current === null || queryId > current) {
+      set({ queryId, results: [], pendingPlugins: null });
+    }
+  },
+  setSearchResults: (searchResults: T_SearchResults) => {
+    if (get().query.length === 0) return;
+    // results can arrive before update_search_query returns the new id
+    get().setQueryId(searchResults.query_id);
+    if (searchResults.query_id !== get().queryId) return;
+    set({
+      results: searchResults.results,
+      pendingPlugins: searchResults.pending,
+    });
+  },
   setHistory: (history: string[]) => set({ history }),
   setSelected: (selected: number | null) => set({ selected }),
   setSelectedHistory: (selectedHistory: number | null) =>
     set({ selectedHistory }),
+  setActionError: (actionError: string | null) => set({ actionError }),
 }));
@@ -8,27 +8,89 @@ export type T_Metadata = {
   url: string;
 };
 
+export type T_ConfigValue =
+  | boolean
+  | number
+  | string
+  | string[]
+  | Record<string, T_ConfigValue>[];
+
+export type T_ConfigField = {
+  key: string;
+  type:
+    | "path"
+    | "bool"
+    | "int"
+    | "enum"
+    | "string"
+    | "string_list"
+    | "table_list";
+  default: T_ConfigValue;
+  description: string;
+  must_exist?: boolean;
+  directory?: boolean;
+  min?: number | null;
+  max?: number | null;
+  options?: string[];
+  fields?: T_ConfigField[];
+};
+
+export type T_ConfigError = {
+  kind: string;
+  key?: string;
+  plugin?: string;
+  expected?: string;
+  min?: number | null;
+  max?: number | null;
+  options?: string[];
+  path?: string;
+  message?: string;
+};
+
+export type T_PluginStatus =
+  | { state: "healthy" }
+  | { state: "degraded"; message: string }
+  | { state: "failed"; message: string }
+  | { state: "crashed"; message: string; restart_in: number };
+
 export type T_Plugin = {
   metadata: T_Metadata;
-  config: Record<string, string>;
+  config: Record<string, T_ConfigValue>;
+  schema: T_ConfigField[];
+  enabled: boolean;
+  status: T_PluginStatus;
+  crashes: number;
 };
 
 export type T_SettingsStore = {
   query: string;
   plugins: Record<string, T_Plugin>;
   selectedPlugin: string | null;
+  configError: string | null;
+  keymapErrors: string[];
   setPlugins: (plugins: Record<string, T_Plugin>) => void;
   refreshPlugins: () => void;
+  setPluginEnabled: (name: string, enabled: boolean) => void;
+  reloadPlugin: (name: string) => void;
   updatePlugin: (plugin: T_Plugin) => void;
+  updatePluginConfig: (
+    name: string,
+    key: string,
+    value: T_ConfigValue,
+  ) => Promise<T_ConfigError | null>;
   setSelectedPlugin: (selectedPlugin: string | null) => void;
   getSelectedPlugin: () => T_Plugin | null;
   setQuery: (query: string) => void;
+  setConfigError: (configError: string | null) => void;
+  refreshKeymapErrors: () => void;
 };
 
 export const useSettingsStore = create<T_SettingsStore>((set, get) => ({
   query: "",
   plugins: {},
   selectedPlugin: null,
+  configError: null,
+  keymapErrors: [],
 
   setPlugins: (plugins: Record<string, T_Plugin>) => set({ plugins }),
   refreshPlugins: () => {
@@ -36,6 +98,16 @@ export const useSettingsStore = create<T_SettingsStore>((set, get) => ({
       set({ plugins });
     });
   },
+  setPluginEnabled: (name: string, enabled: boolean) => {
+    invoke(enabled ? "enable_plugin" : "disable_plugin", {
+      pluginName: name,
+    }).then(() => get().refreshPlugins());
+  },
+  reloadPlugin: (name: string) => {
+    invoke("reload_plugin", { pluginName: name }).then(() =>
+      get().refreshPlugins(),
+    );
+  },
   updatePlugin: (plugin: T_Plugin) =>
     set((state) => ({
       plugins: {
@@ -43,6 +115,17 @@ export const useSettingsStore = create<T_SettingsStore>((set, get) => ({
         [plugin.metadata.name]: plugin,
       },
     })),
+  updatePluginConfig: (name: string, key: string, value: T_ConfigValue) =>
+    invoke("update_plugin_config", { pluginName: name, key, value })
+      .then(() => {
+        const plugin = get().plugins[name];
+        get().updatePlugin({
+          ...plugin,
+          config: { ...plugin.config, [key]: value },
+        });
+        return null;
+      })
+      .catch((error: T_ConfigError) => error),
   setSelectedPlugin: (selectedPlugin: string | null) => set({ selectedPlugin }),
   getSelectedPlugin: () => {
     const { selectedPlugin, plugins } = get();
@@ -52,4 +135,10 @@ export const useSettingsStore = create<T_SettingsStore>((set, get) => ({
     return plugins[selectedPlugin];
   },
   setQuery: (query: string) => set({ query }),
+  setConfigError: (configError: string | null) => set({ configError }),
+  refreshKeymapErrors: () => {
+    invoke<string[]>("get_keymap_errors").then((keymapErrors) => {
+      set({ keymapErrors });
+    });
+  },
 }));
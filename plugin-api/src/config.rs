@@ -0,0 +1,473 @@
+use std::{collections::HashMap, fmt};
+
+/// A single configuration value, serialized as the bare JSON/TOML value
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ConfigValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    List(Vec<String>),
+    /// Tables sharing the fields of a `ConfigKind::TableList`
+    Tables(Vec<Config>),
+}
+
+impl ConfigValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn as_list(&self) -> Option<&[String]> {
+        match self {
+            Self::List(value) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_tables(&self) -> Option<&[Config]> {
+        match self {
+            Self::Tables(value) => Some(value),
+            // an empty array can't be told apart from an empty string list
+            Self::List(value) if value.is_empty() => Some(&[]),
+            _ => None,
+        }
+    }
+}
+
+pub type Config = HashMap<String, ConfigValue>;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigKind {
+    Path {
+        #[serde(default)]
+        must_exist: bool,
+        #[serde(default)]
+        directory: bool,
+    },
+    Bool,
+    Int {
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    Enum {
+        options: Vec<String>,
+    },
+    String,
+    StringList,
+    /// A list of tables, e.g. `[[plugins.name.key]]` sections in fin.toml, each holding the
+    /// given fields. Missing fields take their default.
+    TableList {
+        fields: Vec<ConfigField>,
+    },
+}
+
+impl ConfigKind {
+    /// The loosest kind able to hold the value, used when a plugin only provides a default
+    pub fn infer(value: &ConfigValue) -> Self {
+        match value {
+            ConfigValue::Bool(_) => Self::Bool,
+            ConfigValue::Int(_) => Self::Int {
+                min: None,
+                max: None,
+            },
+            ConfigValue::String(_) => Self::String,
+            ConfigValue::List(_) => Self::StringList,
+            ConfigValue::Tables(tables) => {
+                let mut fields: Vec<ConfigField> = tables
+                    .first()
+                    .map(|table| {
+                        table
+                            .iter()
+                            .map(|(key, value)| {
+                                ConfigField::new(key, Self::infer(value), value.clone(), "")
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                fields.sort_by(|a, b| a.key.cmp(&b.key));
+                Self::TableList { fields }
+            }
+        }
+    }
+    fn expected_type(&self) -> &'static str {
+        match self {
+            Self::Path { .. } => "path",
+            Self::Bool => "bool",
+            Self::Int { .. } => "int",
+            Self::Enum { .. } => "enum",
+            Self::String => "string",
+            Self::StringList => "string list",
+            Self::TableList { .. } => "list of tables",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConfigField {
+    pub key: String,
+    #[serde(flatten)]
+    pub kind: ConfigKind,
+    pub default: ConfigValue,
+    pub description: String,
+}
+
+impl ConfigField {
+    pub fn new(key: &str, kind: ConfigKind, default: ConfigValue, description: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            kind,
+            default,
+            description: description.to_string(),
+        }
+    }
+
+    pub fn validate(&self, value: &ConfigValue) -> Result<(), ConfigError> {
+        let invalid_type = || ConfigError::InvalidType {
+            key: self.key.clone(),
+            expected: self.kind.expected_type(),
+        };
+        match (&self.kind, value) {
+            (
+                ConfigKind::Path {
+                    must_exist,
+                    directory,
+                },
+                ConfigValue::String(path),
+            ) => {
+                let metadata = std::fs::metadata(path);
+                if (*must_exist || *directory) && metadata.is_err() {
+                    return Err(ConfigError::PathNotFound {
+                        key: self.key.clone(),
+                        path: path.clone(),
+                    });
+                }
+                if *directory && !metadata.map(|metadata| metadata.is_dir()).unwrap_or(false) {
+                    return Err(ConfigError::NotADirectory {
+                        key: self.key.clone(),
+                        path: path.clone(),
+                    });
+                }
+                Ok(())
+            }
+            (ConfigKind::Bool, ConfigValue::Bool(_)) => Ok(()),
+            (ConfigKind::Int { min, max }, ConfigValue::Int(value)) => {
+                if min.is_some_and(|min| *value < min) || max.is_some_and(|max| *value > max) {
+                    return Err(ConfigError::OutOfRange {
+                        key: self.key.clone(),
+                        min: *min,
+                        max: *max,
+                    });
+                }
+                Ok(())
+            }
+            (ConfigKind::Enum { options }, ConfigValue::String(value)) => {
+                if !options.contains(value) {
+                    return Err(ConfigError::InvalidOption {
+                        key: self.key.clone(),
+                        options: options.clone(),
+                    });
+                }
+                Ok(())
+            }
+            (ConfigKind::String, ConfigValue::String(_)) => Ok(()),
+            (ConfigKind::StringList, ConfigValue::List(_)) => Ok(()),
+            (ConfigKind::TableList { fields }, value) => {
+                let tables = value.as_tables().ok_or_else(invalid_type)?;
+                for (index, table) in tables.iter().enumerate() {
+                    for (key, value) in table {
+                        let field = fields.iter().find(|field| &field.key == key);
+                        let key = format!("{}[{}].{}", self.key, index, key);
+                        let Some(field) = field else {
+                            return Err(ConfigError::UnknownField { key });
+                        };
+                        // reports errors with the full key, e.g. `roots[1].path`
+                        Self {
+                            key,
+                            ..field.clone()
+                        }
+                        .validate(value)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(invalid_type()),
+        }
+    }
+}
+
+pub type ConfigSchema = Vec<ConfigField>;
+
+pub fn defaults(schema: &[ConfigField]) -> Config {
+    schema
+        .iter()
+        .map(|field| (field.key.clone(), field.default.clone()))
+        .collect()
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigError {
+    UnknownPlugin {
+        plugin: String,
+    },
+    UnknownKey {
+        plugin: String,
+        key: String,
+    },
+    /// A table in a `ConfigKind::TableList` has a field the list doesn't know
+    UnknownField {
+        key: String,
+    },
+    InvalidType {
+        key: String,
+        expected: &'static str,
+    },
+    OutOfRange {
+        key: String,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    InvalidOption {
+        key: String,
+        options: Vec<String>,
+    },
+    PathNotFound {
+        key: String,
+        path: String,
+    },
+    NotADirectory {
+        key: String,
+        path: String,
+    },
+    SaveFailed {
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPlugin { plugin } => write!(f, "plugin {} not found", plugin),
+            Self::UnknownKey { plugin, key } => {
+                write!(f, "plugin {} has no config key `{}`", plugin, key)
+            }
+            Self::UnknownField { key } => write!(f, "`{}` is not a known field", key),
+            Self::InvalidType { key, expected } => {
+                write!(f, "`{}` should be of type {}", key, expected)
+            }
+            Self::OutOfRange { key, min, max } => match (min, max) {
+                (Some(min), Some(max)) => {
+                    write!(f, "`{}` should be between {} and {}", key, min, max)
+                }
+                (Some(min), None) => write!(f, "`{}` should be at least {}", key, min),
+                (None, Some(max)) => write!(f, "`{}` should be at most {}", key, max),
+                (None, None) => write!(f, "`{}` is out of range", key),
+            },
+            Self::InvalidOption { key, options } => {
+                write!(f, "`{}` should be one of {}", key, options.join(", "))
+            }
+            Self::PathNotFound { key, path } => write!(f, "`{}`: {} does not exist", key, path),
+            Self::NotADirectory { key, path } => {
+                write!(f, "`{}`: {} is not a directory", key, path)
+            }
+            Self::SaveFailed { message } => write!(f, "failed to save config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(min: Option<i64>, max: Option<i64>) -> ConfigField {
+        ConfigField::new(
+            "depth",
+            ConfigKind::Int { min, max },
+            ConfigValue::Int(1),
+            "",
+        )
+    }
+
+    fn roots() -> ConfigField {
+        ConfigField::new(
+            "roots",
+            ConfigKind::TableList {
+                fields: vec![
+                    ConfigField::new(
+                        "path",
+                        ConfigKind::String,
+                        ConfigValue::String(String::new()),
+                        "",
+                    ),
+                    int(Some(1), Some(10)),
+                ],
+            },
+            ConfigValue::Tables(vec![]),
+            "",
+        )
+    }
+
+    fn table(fields: &[(&str, ConfigValue)]) -> Config {
+        fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn checks_int_bounds() {
+        let field = int(Some(1), Some(10));
+        assert!(field.validate(&ConfigValue::Int(1)).is_ok());
+        assert!(field.validate(&ConfigValue::Int(10)).is_ok());
+        assert!(matches!(
+            field.validate(&ConfigValue::Int(0)),
+            Err(ConfigError::OutOfRange {
+                min: Some(1),
+                max: Some(10),
+                ..
+            })
+        ));
+        assert!(matches!(
+            field.validate(&ConfigValue::Int(11)),
+            Err(ConfigError::OutOfRange { .. })
+        ));
+        assert!(int(None, None)
+            .validate(&ConfigValue::Int(i64::MIN))
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_values_of_another_type() {
+        assert!(matches!(
+            int(None, None).validate(&ConfigValue::String("1".to_string())),
+            Err(ConfigError::InvalidType {
+                expected: "int",
+                ..
+            })
+        ));
+        assert!(matches!(
+            roots().validate(&ConfigValue::Bool(true)),
+            Err(ConfigError::InvalidType {
+                expected: "list of tables",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn checks_enum_options() {
+        let field = ConfigField::new(
+            "sort",
+            ConfigKind::Enum {
+                options: vec!["name".to_string(), "size".to_string()],
+            },
+            ConfigValue::String("name".to_string()),
+            "",
+        );
+        assert!(field
+            .validate(&ConfigValue::String("size".to_string()))
+            .is_ok());
+        assert!(matches!(
+            field.validate(&ConfigValue::String("date".to_string())),
+            Err(ConfigError::InvalidOption { .. })
+        ));
+    }
+
+    #[test]
+    fn checks_paths() {
+        let path = |must_exist, directory| {
+            ConfigField::new(
+                "path",
+                ConfigKind::Path {
+                    must_exist,
+                    directory,
+                },
+                ConfigValue::String(String::new()),
+                "",
+            )
+        };
+        let manifest =
+            ConfigValue::String(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml").to_string());
+        let missing = ConfigValue::String("/does/not/exist".to_string());
+        assert!(path(false, false).validate(&missing).is_ok());
+        assert!(matches!(
+            path(true, false).validate(&missing),
+            Err(ConfigError::PathNotFound { .. })
+        ));
+        assert!(path(true, false).validate(&manifest).is_ok());
+        assert!(matches!(
+            path(false, true).validate(&manifest),
+            Err(ConfigError::NotADirectory { .. })
+        ));
+        let directory = ConfigValue::String(env!("CARGO_MANIFEST_DIR").to_string());
+        assert!(path(true, true).validate(&directory).is_ok());
+    }
+
+    #[test]
+    fn validates_table_fields() {
+        let value = ConfigValue::Tables(vec![
+            table(&[("path", ConfigValue::String("/home".to_string()))]),
+            table(&[
+                ("path", ConfigValue::String("/tmp".to_string())),
+                ("depth", ConfigValue::Int(3)),
+            ]),
+        ]);
+        assert!(roots().validate(&value).is_ok());
+        // written as `roots = []`, which parses as an empty string list
+        assert!(roots().validate(&ConfigValue::List(vec![])).is_ok());
+    }
+
+    #[test]
+    fn reports_table_errors_with_their_key_path() {
+        let out_of_range = ConfigValue::Tables(vec![
+            table(&[("depth", ConfigValue::Int(3))]),
+            table(&[("depth", ConfigValue::Int(30))]),
+        ]);
+        match roots().validate(&out_of_range) {
+            Err(ConfigError::OutOfRange { key, .. }) => assert_eq!(key, "roots[1].depth"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let unknown = ConfigValue::Tables(vec![table(&[("hidden", ConfigValue::Bool(true))])]);
+        match roots().validate(&unknown) {
+            Err(ConfigError::UnknownField { key }) => assert_eq!(key, "roots[0].hidden"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn infers_kinds_from_defaults() {
+        assert_eq!(
+            ConfigKind::infer(&ConfigValue::Int(3)),
+            ConfigKind::Int {
+                min: None,
+                max: None
+            }
+        );
+        let tables = ConfigValue::Tables(vec![table(&[
+            ("path", ConfigValue::String(String::new())),
+            ("depth", ConfigValue::Int(1)),
+        ])]);
+        let ConfigKind::TableList { fields } = ConfigKind::infer(&tables) else {
+            panic!("expected a table list");
+        };
+        let keys: Vec<&str> = fields.iter().map(|field| field.key.as_str()).collect();
+        assert_eq!(keys, ["depth", "path"]);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::config;
+use std::fmt;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Failures reported by a plugin, surfaced as its status instead of stopping the launcher
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Config(config::ConfigError),
+    Script(mlua::Error),
+    /// Any other failure, e.g. from a plugin's own dependencies
+    Plugin(String),
+}
+
+impl Error {
+    pub fn other(error: impl fmt::Display) -> Self {
+        Self::Plugin(error.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Config(error) => write!(f, "{}", error),
+            // the first line holds the message, the rest is Lua's stack traceback
+            Self::Script(error) => {
+                write!(
+                    f,
+                    "{}",
+                    error.to_string().lines().next().unwrap_or_default()
+                )
+            }
+            Self::Plugin(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<config::ConfigError> for Error {
+    fn from(error: config::ConfigError) -> Self {
+        Self::Config(error)
+    }
+}
+
+impl From<mlua::Error> for Error {
+    fn from(error: mlua::Error) -> Self {
+        Self::Script(error)
+    }
+}
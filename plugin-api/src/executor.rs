@@ -0,0 +1,506 @@
+use crate::{lock, Action, PluginManager};
+use directories::BaseDirs;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::{
+    ffi::OsStr,
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+#[cfg(target_os = "macos")]
+const OPEN_PROGRAM: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const OPEN_PROGRAM: &str = "xdg-open";
+/// Terminal emulators tried in order when `$TERMINAL` isn't set, with the arguments preceding
+/// the command to run
+#[cfg(not(target_os = "macos"))]
+const TERMINALS: [(&str, &[&str]); 6] = [
+    ("x-terminal-emulator", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("konsole", &["-e"]),
+    ("alacritty", &["-e"]),
+    ("kitty", &[]),
+    ("xterm", &["-e"]),
+];
+/// How long typing waits for the launcher to hide and focus to return to the previous window
+const TYPE_DELAY: Duration = Duration::from_millis(200);
+
+/// Why an action couldn't be executed, reported to the user
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ActionError {
+    NotFound {
+        path: String,
+    },
+    /// The command line of an application has no program in it
+    EmptyCommand,
+    /// A helper program the action relies on isn't installed, e.g. `xdotool` for typing
+    ProgramNotFound {
+        program: String,
+    },
+    SpawnFailed {
+        program: String,
+        message: String,
+    },
+    ClipboardFailed {
+        message: String,
+    },
+    InvalidUrl {
+        url: String,
+    },
+    /// A `PluginCallback` names a plugin that isn't loaded or is disabled
+    PluginNotRunning {
+        plugin: String,
+    },
+    /// The picked result or action isn't listed anymore, e.g. because the query changed
+    StaleResult,
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { path } => write!(f, "{} does not exist", path),
+            Self::EmptyCommand => write!(f, "the command to run is empty"),
+            Self::ProgramNotFound { program } => write!(f, "{} is not installed", program),
+            Self::SpawnFailed { program, message } => {
+                write!(f, "failed to run {}: {}", program, message)
+            }
+            Self::ClipboardFailed { message } => {
+                write!(f, "failed to copy to the clipboard: {}", message)
+            }
+            Self::InvalidUrl { url } => write!(f, "{} is not a URL", url),
+            Self::PluginNotRunning { plugin } => write!(f, "plugin {} is not running", plugin),
+            Self::StaleResult => write!(f, "the picked result is no longer listed"),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+/// Executes the actions of picked results. Programs are started detached, so they keep running
+/// after the launcher quits.
+#[derive(Default)]
+pub struct ActionExecutor {
+    // kept open, on X11 the copied text is only available while its owner is alive
+    clipboard: Mutex<Option<arboard::Clipboard>>,
+}
+
+impl ActionExecutor {
+    /// Runs the action, plugin callbacks are handed to the plugin's worker
+    pub fn execute(
+        &self,
+        action: &Action,
+        plugin_manager: &PluginManager,
+    ) -> Result<(), ActionError> {
+        match action {
+            Action::Open(path) => open(path),
+            Action::LaunchApplication(application) => launch(application),
+            Action::RunCommand {
+                program,
+                args,
+                cwd,
+                terminal,
+            } => run_command(program, args, cwd.as_deref(), *terminal),
+            Action::OpenUrl(url) => open_url(url),
+            Action::RevealInFileManager(path) => reveal(path),
+            Action::CopyText(text) => self.copy(text),
+            Action::TypeText(text) => type_text(text),
+            Action::PluginCallback {
+                plugin,
+                id,
+                payload,
+            } => plugin_manager.send_callback(plugin, id, payload),
+        }
+    }
+
+    fn copy(&self, text: &str) -> Result<(), ActionError> {
+        let clipboard_failed = |error: arboard::Error| ActionError::ClipboardFailed {
+            message: error.to_string(),
+        };
+        let mut clipboard = lock(&self.clipboard);
+        let clipboard = match &mut *clipboard {
+            Some(clipboard) => clipboard,
+            empty => empty.insert(arboard::Clipboard::new().map_err(clipboard_failed)?),
+        };
+        clipboard.set_text(text).map_err(clipboard_failed)
+    }
+}
+
+/// Opens a file or directory with its default application
+fn open(path: &str) -> Result<(), ActionError> {
+    existing(path)?;
+    spawn(OPEN_PROGRAM, &[path], None)
+}
+
+/// Opens a macOS bundle, or runs the command line of a `.desktop` entry
+fn launch(application: &str) -> Result<(), ActionError> {
+    if application.ends_with(".app") {
+        return open(application);
+    }
+    match split_command_line(application).split_first() {
+        Some((program, arguments)) => spawn(program, arguments, None),
+        None => Err(ActionError::EmptyCommand),
+    }
+}
+
+fn run_command(
+    program: &str,
+    arguments: &[String],
+    cwd: Option<&str>,
+    terminal: bool,
+) -> Result<(), ActionError> {
+    let cwd = cwd.map(existing).transpose()?;
+    if terminal {
+        run_in_terminal(program, arguments, cwd)
+    } else {
+        spawn(program, arguments, cwd)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn run_in_terminal(
+    program: &str,
+    arguments: &[String],
+    cwd: Option<&Path>,
+) -> Result<(), ActionError> {
+    let mut command: Vec<String> = std::iter::once(program)
+        .chain(arguments.iter().map(String::as_str))
+        .map(shell_quote)
+        .collect();
+    if let Some(cwd) = cwd {
+        command.splice(
+            0..0,
+            [
+                "cd".to_string(),
+                shell_quote(&cwd.to_string_lossy()),
+                "&&".to_string(),
+            ],
+        );
+    }
+    spawn(
+        "osascript",
+        &[
+            "-e",
+            "on run argv",
+            "-e",
+            "tell application \"Terminal\" to do script (item 1 of argv)",
+            "-e",
+            "tell application \"Terminal\" to activate",
+            "-e",
+            "end run",
+            &command.join(" "),
+        ],
+        None,
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn run_in_terminal(
+    program: &str,
+    arguments: &[String],
+    cwd: Option<&Path>,
+) -> Result<(), ActionError> {
+    let (terminal, prefix) = match std::env::var("TERMINAL")
+        .ok()
+        .filter(|terminal| !terminal.is_empty())
+    {
+        Some(terminal) => (terminal, vec!["-e"]),
+        None => TERMINALS
+            .iter()
+            .find(|(terminal, _)| find_program(terminal).is_some())
+            .map(|(terminal, prefix)| (terminal.to_string(), prefix.to_vec()))
+            .ok_or_else(|| ActionError::ProgramNotFound {
+                program: "a terminal emulator".to_string(),
+            })?,
+    };
+    let arguments: Vec<&str> = prefix
+        .into_iter()
+        .chain(std::iter::once(program))
+        .chain(arguments.iter().map(String::as_str))
+        .collect();
+    spawn(&terminal, &arguments, cwd)
+}
+
+/// Quotes an argument for `sh`
+#[cfg(target_os = "macos")]
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+fn open_url(url: &str) -> Result<(), ActionError> {
+    // a scheme such as `https:` or `mailto:`, paths are opened with `Action::Open`
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    });
+    if !has_scheme {
+        return Err(ActionError::InvalidUrl {
+            url: url.to_string(),
+        });
+    }
+    spawn(OPEN_PROGRAM, &[url], None)
+}
+
+#[cfg(target_os = "macos")]
+fn reveal(path: &str) -> Result<(), ActionError> {
+    existing(path)?;
+    spawn("open", &["-R", path], None)
+}
+
+/// Asks the file manager to select the entry through the freedesktop `FileManager1` interface,
+/// file managers that don't implement it open the parent directory instead
+#[cfg(not(target_os = "macos"))]
+fn reveal(path: &str) -> Result<(), ActionError> {
+    let parent = existing(path)?
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .to_string_lossy()
+        .to_string();
+    let item = format!("array:string:{}", file_url(path));
+    // waiting for the reply would hold up the launcher
+    std::thread::spawn(move || {
+        let shown = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                "--reply-timeout=1000",
+                "--dest=org.freedesktop.FileManager1",
+                "--type=method_call",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+                &item,
+                "string:",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !shown {
+            if let Err(error) = spawn(OPEN_PROGRAM, &[&parent], None) {
+                println!("Failed to reveal {}: {}", parent, error);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// `file://` URL of an absolute path, everything but unreserved characters and `/` is
+/// percent-encoded
+#[cfg(not(target_os = "macos"))]
+fn file_url(path: &str) -> String {
+    let mut url = String::from("file://");
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~') {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+fn type_text(text: &str) -> Result<(), ActionError> {
+    let (program, arguments) = typing_command(text);
+    if find_program(program).is_none() {
+        return Err(ActionError::ProgramNotFound {
+            program: program.to_string(),
+        });
+    }
+    std::thread::spawn(move || {
+        std::thread::sleep(TYPE_DELAY);
+        if let Err(error) = spawn(program, &arguments, None) {
+            println!("Failed to type text: {}", error);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn typing_command(text: &str) -> (&'static str, Vec<String>) {
+    let arguments = [
+        "-e",
+        "on run argv",
+        "-e",
+        "tell application \"System Events\" to keystroke (item 1 of argv)",
+        "-e",
+        "end run",
+        text,
+    ];
+    ("osascript", arguments.map(String::from).to_vec())
+}
+
+/// `wtype` on Wayland, `xdotool` on X11
+#[cfg(not(target_os = "macos"))]
+fn typing_command(text: &str) -> (&'static str, Vec<String>) {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wtype", vec!["--".to_string(), text.to_string()])
+    } else {
+        let arguments = ["type", "--clearmodifiers", "--", text];
+        ("xdotool", arguments.map(String::from).to_vec())
+    }
+}
+
+fn existing(path: &str) -> Result<&Path, ActionError> {
+    let path = Path::new(path);
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(ActionError::NotFound {
+            path: path.to_string_lossy().to_string(),
+        })
+    }
+}
+
+/// Looks `program` up in `$PATH` like a shell would
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program)).filter(|path| path.is_file());
+    }
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|directory| directory.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Starts `program` in `cwd`, or the home directory, and on unix in a process group of its own,
+/// so signals sent to the launcher don't reach it
+fn spawn(
+    program: &str,
+    arguments: &[impl AsRef<OsStr>],
+    cwd: Option<&Path>,
+) -> Result<(), ActionError> {
+    let mut command = Command::new(program);
+    command
+        .args(arguments)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    command.process_group(0);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    } else if let Some(base_dirs) = BaseDirs::new() {
+        command.current_dir(base_dirs.home_dir());
+    }
+    let mut child = command.spawn().map_err(|error| ActionError::SpawnFailed {
+        program: program.to_string(),
+        message: error.to_string(),
+    })?;
+    // reap the process once it exits, it lingers as a zombie otherwise
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Splits a command line into arguments the way `.desktop` entries quote them, arguments
+/// containing spaces are wrapped in `"` and `\` escapes the next character inside quotes
+pub fn split_command_line(command: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut argument: Option<String> = None;
+    let mut quoted = false;
+    let mut characters = command.chars();
+    while let Some(c) = characters.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                argument.get_or_insert_with(String::new);
+            }
+            '\\' if quoted => {
+                if let Some(next) = characters.next() {
+                    argument.get_or_insert_with(String::new).push(next);
+                }
+            }
+            c if c.is_whitespace() && !quoted => arguments.extend(argument.take()),
+            c => argument.get_or_insert_with(String::new).push(c),
+        }
+    }
+    arguments.extend(argument);
+    arguments
+}
+
+/// Joins arguments into a command line that `split_command_line` splits into the same arguments
+pub fn join_command_line(arguments: &[String]) -> String {
+    arguments
+        .iter()
+        .map(|argument| {
+            let plain = !argument.is_empty()
+                && !argument
+                    .chars()
+                    .any(|c| c.is_whitespace() || c == '"' || c == '\\');
+            if plain {
+                return argument.clone();
+            }
+            let mut quoted = String::from('"');
+            for c in argument.chars() {
+                if c == '"' || c == '\\' {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted.push('"');
+            quoted
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(command: &str) -> Vec<String> {
+        split_command_line(command)
+    }
+
+    #[test]
+    fn splits_on_whitespace_outside_quotes() {
+        assert_eq!(split("ls  -la\t/tmp "), ["ls", "-la", "/tmp"]);
+        assert_eq!(
+            split("\"/opt/My App/app\" --flag"),
+            ["/opt/My App/app", "--flag"]
+        );
+        assert_eq!(split("--name=\"two words\""), ["--name=two words"]);
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn keeps_empty_quoted_arguments() {
+        assert_eq!(split("app \"\" last"), ["app", "", "last"]);
+    }
+
+    #[test]
+    fn escapes_only_inside_quotes() {
+        assert_eq!(split(r#"echo "a \"b\" \\c""#), ["echo", r#"a "b" \c"#]);
+        assert_eq!(split(r"C:\path\to"), [r"C:\path\to"]);
+    }
+
+    #[test]
+    fn joins_into_a_command_line_that_splits_back() {
+        let arguments: Vec<String> = ["app", "two  spaces", "", r#"quote"and\slash"#, "%u"]
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let command = join_command_line(&arguments);
+        assert_eq!(command, r#"app "two  spaces" "" "quote\"and\\slash" %u"#);
+        assert_eq!(split(&command), arguments);
+    }
+
+    #[test]
+    fn only_accepts_urls_with_a_scheme() {
+        assert!(matches!(
+            open_url("/home/me/file.txt"),
+            Err(ActionError::InvalidUrl { .. })
+        ));
+        assert!(matches!(
+            open_url("1http://example.com"),
+            Err(ActionError::InvalidUrl { .. })
+        ));
+    }
+}
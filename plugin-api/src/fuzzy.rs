@@ -0,0 +1,229 @@
+use std::ops::Range;
+
+const MATCH: i32 = 16;
+const GAP_START: i32 = -3;
+const GAP_EXTENSION: i32 = -1;
+/// Matching the character right after the previous one
+const BONUS_CONSECUTIVE: i32 = 8;
+/// Matching the first character of a path segment
+const BONUS_SEGMENT: i32 = 10;
+/// Matching the first character of a word, e.g. after `_` or in camelCase
+const BONUS_WORD: i32 = 8;
+/// Matching inside the file name rather than a parent directory
+const BONUS_BASENAME: i32 = 4;
+/// Best possible score of a single character, used to normalise scores
+const MAX_CHAR_SCORE: i32 = MATCH + BONUS_SEGMENT + BONUS_BASENAME;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub score: i32,
+    /// Matched character (not byte) ranges of the path, sorted and non-overlapping
+    pub ranges: Vec<Range<usize>>,
+    query_length: usize,
+}
+
+impl Match {
+    /// The score in `0.0..=1.0`, comparable between queries of different lengths
+    pub fn normalized_score(&self) -> f32 {
+        if self.query_length == 0 {
+            return 0.0;
+        }
+        (self.score as f32 / (self.query_length as i32 * MAX_CHAR_SCORE) as f32).clamp(0.0, 1.0)
+    }
+}
+
+/// Matches every whitespace separated term of the query as a subsequence of the path, scoring
+/// them Smith-Waterman style so tight matches on word and segment starts in the file name win
+pub fn fuzzy_match(query: &str, path: &str) -> Option<Match> {
+    let path: Vec<char> = path.chars().collect();
+    let folded: Vec<char> = path.iter().map(|c| lowercase(*c)).collect();
+    let basename_start = path
+        .iter()
+        .rposition(|c| *c == '/')
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    let bonuses: Vec<i32> = (0..path.len())
+        .map(|index| {
+            let bonus = match index.checked_sub(1).map(|previous| path[previous]) {
+                None | Some('/') => BONUS_SEGMENT,
+                Some(previous) if !previous.is_alphanumeric() => BONUS_WORD,
+                Some(previous) if previous.is_lowercase() && path[index].is_uppercase() => {
+                    BONUS_WORD
+                }
+                _ => 0,
+            };
+            if index >= basename_start {
+                bonus + BONUS_BASENAME
+            } else {
+                bonus
+            }
+        })
+        .collect();
+
+    let mut score = 0;
+    let mut ranges = vec![];
+    let mut query_length = 0;
+    for term in query.split_whitespace() {
+        let term: Vec<char> = term.chars().map(lowercase).collect();
+        let (term_score, positions) = match_term(&term, &folded, &bonuses)?;
+        score += term_score;
+        query_length += term.len();
+        ranges.extend(positions.into_iter().map(|position| position..position + 1));
+    }
+    if query_length == 0 {
+        return None;
+    }
+
+    // terms may overlap each other, merge their ranges into contiguous ones
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    Some(Match {
+        score,
+        ranges: merged,
+        query_length,
+    })
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Finds the best scoring alignment of the term in the haystack, returning its score and the
+/// matched positions
+fn match_term(term: &[char], haystack: &[char], bonuses: &[i32]) -> Option<(i32, Vec<usize>)> {
+    let (rows, columns) = (term.len(), haystack.len());
+    if rows == 0 || rows > columns {
+        return None;
+    }
+    // scores[i][j]: best score with term[..=i] matched and term[i] matched at haystack[j]
+    let mut scores = vec![vec![None::<i32>; columns]; rows];
+    // where term[i - 1] was matched for the best score in scores[i][j]
+    let mut previous = vec![vec![0usize; columns]; rows];
+
+    for (j, c) in haystack.iter().enumerate() {
+        if *c == term[0] {
+            scores[0][j] = Some(MATCH + bonuses[j]);
+        }
+    }
+    for i in 1..rows {
+        // best score of term[..i] ending at least two characters back, with the gap applied
+        let mut gapped: Option<(i32, usize)> = None;
+        for j in i..columns {
+            if j >= 2 {
+                let extended = gapped.map(|(score, from)| (score + GAP_EXTENSION, from));
+                let started = scores[i - 1][j - 2].map(|score| (score + GAP_START, j - 2));
+                gapped = match (extended, started) {
+                    (Some(extended), Some(started)) if started.0 >= extended.0 => Some(started),
+                    (Some(extended), _) => Some(extended),
+                    (None, started) => started,
+                };
+            }
+            if haystack[j] != term[i] {
+                continue;
+            }
+            let consecutive = scores[i - 1][j - 1].map(|score| (score + BONUS_CONSECUTIVE, j - 1));
+            let best = match (consecutive, gapped) {
+                (Some(consecutive), Some(gapped)) if gapped.0 > consecutive.0 => Some(gapped),
+                (Some(consecutive), _) => Some(consecutive),
+                (None, gapped) => gapped,
+            };
+            if let Some((score, from)) = best {
+                scores[i][j] = Some(score + MATCH + bonuses[j]);
+                previous[i][j] = from;
+            }
+        }
+    }
+
+    // prefer the rightmost end on ties, matches in the file name beat ones in parent directories
+    let (mut column, score) = scores[rows - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| score.map(|score| (j, score)))
+        .max_by_key(|(j, score)| (*score, *j))?;
+    let mut positions = vec![column; rows];
+    for i in (1..rows).rev() {
+        column = previous[i][column];
+        positions[i - 1] = column;
+    }
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The matched ranges as `(start, end)` pairs
+    fn ranges(query: &str, path: &str) -> Vec<(usize, usize)> {
+        fuzzy_match(query, path)
+            .unwrap()
+            .ranges
+            .into_iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    fn score(query: &str, path: &str) -> i32 {
+        fuzzy_match(query, path).unwrap().score
+    }
+
+    #[test]
+    fn requires_every_character_in_order() {
+        assert!(fuzzy_match("dwn", "/home/me/Downloads").is_some());
+        assert!(fuzzy_match("nwd", "/home/me/Downloads").is_none());
+        assert!(fuzzy_match("downloadss", "Downloads").is_none());
+    }
+
+    #[test]
+    fn empty_query_does_not_match() {
+        assert!(fuzzy_match("", "/home").is_none());
+        assert!(fuzzy_match("   ", "/home").is_none());
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(ranges("DOC", "/home/me/documents"), [(9, 12)]);
+    }
+
+    #[test]
+    fn ranges_count_characters_not_bytes() {
+        assert_eq!(ranges("é", "/café"), [(4, 5)]);
+    }
+
+    #[test]
+    fn prefers_the_file_name_over_parent_directories() {
+        assert_eq!(ranges("foo", "/foo/foo"), [(5, 8)]);
+    }
+
+    #[test]
+    fn prefers_consecutive_characters() {
+        assert!(score("abc", "/xabc") > score("abc", "/axbxc"));
+    }
+
+    #[test]
+    fn prefers_word_and_segment_starts() {
+        assert!(score("b", "/a_b") > score("b", "/ab"));
+        assert!(score("b", "/a/b") > score("b", "/a_b"));
+        assert!(score("b", "/aB") > score("b", "/ab"));
+    }
+
+    #[test]
+    fn merges_ranges_of_overlapping_terms() {
+        assert_eq!(ranges("do doc", "Documents"), [(0, 3)]);
+        assert_eq!(ranges("doc ts", "Documents"), [(0, 3), (7, 9)]);
+    }
+
+    #[test]
+    fn normalizes_scores() {
+        assert_eq!(fuzzy_match("a", "a").unwrap().normalized_score(), 1.0);
+        let scattered = fuzzy_match("abc", "/x/yaxxxbxxxc")
+            .unwrap()
+            .normalized_score();
+        assert!(scattered > 0.0 && scattered < 1.0);
+    }
+}
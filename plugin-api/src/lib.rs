@@ -1,7 +1,19 @@
+pub mod config;
+pub mod error;
+pub mod executor;
+pub mod fuzzy;
+pub mod loader;
+pub mod lua;
+pub mod ranking;
+
+pub use error::{Error, Result};
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt::Debug,
-    sync::{Arc, Mutex, MutexGuard},
+    ops::Range,
+    path::Path,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
     time,
 };
 
@@ -13,15 +25,39 @@ pub struct Metadata {
     pub url: Option<String>,
 }
 
+/// A search query dispatched to every plugin, tagged with the generation it belongs to.
+#[derive(Clone, serde::Serialize, Debug, Default)]
+pub struct Query {
+    pub id: u64,
+    pub text: String,
+}
+
 pub trait Plugin: Send + Sync {
-    fn init(
-        &mut self,
-        client_state_arc: Arc<Mutex<ClientState>>,
-        loaded_plugin: Arc<Mutex<LoadedPlugin>>,
-    );
-    fn start(&mut self);
+    /// A failed init marks the plugin as failed, it won't receive queries until it's reloaded
+    fn init(&mut self, loaded_plugin: Arc<Mutex<LoadedPlugin>>) -> Result<()>;
+    /// Called periodically while the plugin is idle, used for background work such as re-indexing
+    fn start(&mut self) -> Result<()>;
+    /// Called after the plugin's config was changed from the settings or fin.toml
+    fn update_config(&mut self, _config: &config::Config) -> Result<()> {
+        Ok(())
+    }
+    fn search(&mut self, query: &Query) -> Vec<SearchResult>;
+    /// Called when one of the plugin's results was picked, e.g. to count application launches
+    fn record_usage(&mut self, _result: &SearchResult) -> Result<()> {
+        Ok(())
+    }
+    /// Called when a result carrying an `Action::PluginCallback` for the plugin was picked
+    fn handle_callback(&mut self, _id: &str, _payload: &str) -> Result<()> {
+        Ok(())
+    }
+    /// Called on the stopped plugin when it's reloaded from the settings, before fresh instances
+    /// are cloned from it again, e.g. to read a script from disk again
+    fn reload(&mut self) -> Result<()> {
+        Ok(())
+    }
     fn get_metadata(&self) -> Metadata;
-    fn get_config(&self) -> HashMap<String, String>;
+    /// Declares the config keys the plugin understands along with their defaults
+    fn get_config_schema(&self) -> config::ConfigSchema;
     fn destroy(&mut self);
     fn clone_box(&self) -> Box<dyn Plugin>;
 }
@@ -32,23 +68,131 @@ impl Clone for Box<dyn Plugin> {
     }
 }
 
-#[derive(Clone, serde::Serialize, Debug)]
+/// Health of a plugin's running instance
+#[derive(Clone, serde::Serialize, Debug, Default, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PluginStatus {
+    #[default]
+    Healthy,
+    /// Still answering queries, but its last background task or config update failed
+    Degraded { message: String },
+    /// Failed to initialise and doesn't answer queries
+    Failed { message: String },
+    /// Panicked and is restarted after `restart_in` seconds
+    Crashed { message: String, restart_in: u64 },
+}
+
+/// What happens when a result is picked, executed by `executor::ActionExecutor`
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub enum Action {
+    /// Opens a file or directory with its default application
     Open(String),
+    /// Runs the command line of a `.desktop` entry or opens a macOS `.app` bundle
     LaunchApplication(String),
-    Copy,
+    RunCommand {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// Defaults to the home directory
+        #[serde(default)]
+        cwd: Option<String>,
+        /// Runs the command in a terminal emulator, for programs with output to read
+        #[serde(default)]
+        terminal: bool,
+    },
+    OpenUrl(String),
+    /// Shows the file or directory selected in its parent directory
+    RevealInFileManager(String),
+    CopyText(String),
+    /// Types the text into the window that was focused before the launcher
+    TypeText(String),
+    /// Hands the payload to `Plugin::handle_callback` of the plugin, for behaviour the core
+    /// doesn't know about
+    PluginCallback {
+        plugin: String,
+        id: String,
+        #[serde(default)]
+        payload: String,
+    },
 }
 
-#[derive(Clone, serde::Serialize, Debug)]
+impl Action {
+    /// The file, application or URL the action opens, results sharing one are duplicates
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Self::Open(target)
+            | Self::LaunchApplication(target)
+            | Self::OpenUrl(target)
+            | Self::RevealInFileManager(target) => Some(target),
+            Self::RunCommand { .. }
+            | Self::CopyText(_)
+            | Self::TypeText(_)
+            | Self::PluginCallback { .. } => None,
+        }
+    }
+
+    /// Shown in the action panel when the plugin doesn't label the action
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Open(_) => "Open",
+            Self::LaunchApplication(_) => "Launch",
+            Self::RunCommand { .. } | Self::PluginCallback { .. } => "Run",
+            Self::OpenUrl(_) => "Open URL",
+            Self::RevealInFileManager(_) => "Show in file manager",
+            Self::CopyText(_) => "Copy",
+            Self::TypeText(_) => "Type",
+        }
+    }
+}
+
+/// One of the actions of a result, listed in the action panel while the result is selected
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+pub struct ResultAction {
+    pub label: String,
+    pub action: Action,
+    /// Keys running the action while the result is selected, e.g. `Ctrl+Enter` or `Alt+C`
+    #[serde(default)]
+    pub shortcut: Option<String>,
+}
+
+impl ResultAction {
+    pub fn new(label: &str, action: Action) -> Self {
+        Self {
+            label: label.to_string(),
+            action,
+            shortcut: None,
+        }
+    }
+    pub fn with_shortcut(mut self, shortcut: &str) -> Self {
+        self.shortcut = Some(shortcut.to_string());
+        self
+    }
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub struct SearchResult {
     title: String,
     description: Option<String>,
     icon: Option<Icon>,
-    action: Option<Action>,
+    /// The first action runs when the result is picked, the rest are offered in its action panel
+    #[serde(default)]
+    actions: Vec<ResultAction>,
+    /// Boosts the result in the merged ranking, out of `u8::MAX`
     priority: Option<u8>,
+    /// How well the result matches the query in `0.0..=1.0`, derived from the title if unset
+    #[serde(default)]
+    score: Option<f32>,
+    /// Character (not byte) ranges of the title that matched the query, for highlighting
+    #[serde(default)]
+    title_matches: Vec<Range<usize>>,
+    #[serde(default)]
+    description_matches: Vec<Range<usize>>,
+    /// The plugin that returned the result, set when results are merged
+    #[serde(default)]
+    plugin: Option<String>,
 }
 
-#[derive(Clone, serde::Serialize, Debug)]
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
 pub enum Icon {
     File,
     Folder,
@@ -70,128 +214,665 @@ impl SearchResult {
             title,
             description,
             icon,
-            action,
+            actions: action
+                .into_iter()
+                .map(|action| ResultAction::new(action.label(), action))
+                .collect(),
             priority,
+            score: None,
+            title_matches: vec![],
+            description_matches: vec![],
+            plugin: None,
+        }
+    }
+    /// The action running when the result is picked
+    pub fn action(&self) -> Option<&Action> {
+        self.actions.first().map(|action| &action.action)
+    }
+    pub fn actions(&self) -> &[ResultAction] {
+        &self.actions
+    }
+    pub fn plugin(&self) -> Option<&str> {
+        self.plugin.as_deref()
+    }
+    pub fn with_score(mut self, score: f32) -> Self {
+        self.score = Some(score);
+        self
+    }
+    /// Adds actions after the one passed to `new`, in the order they're listed in the panel
+    pub fn with_actions(mut self, actions: Vec<ResultAction>) -> Self {
+        self.actions.extend(actions);
+        self
+    }
+    pub fn with_matches(
+        mut self,
+        title_matches: Vec<Range<usize>>,
+        description_matches: Vec<Range<usize>>,
+    ) -> Self {
+        self.title_matches = title_matches;
+        self.description_matches = description_matches;
+        self
+    }
+    /// Identifies the result across queries when recording how often it's picked
+    pub fn usage_key(&self) -> String {
+        match self.action().and_then(Action::target) {
+            Some(target) => target.to_string(),
+            None => self.title.clone(),
         }
     }
 }
 
+/// Results contributed by a single plugin for a query generation.
+#[derive(Clone, serde::Serialize, Debug)]
+pub struct ResultBatch {
+    pub query_id: u64,
+    pub plugin: String,
+    pub results: Vec<SearchResult>,
+    /// Set once the plugin has nothing more to contribute for this query
+    pub finished: bool,
+}
+
+/// The merged and ranked results of every plugin for a query generation
+#[derive(Clone, serde::Serialize, Debug)]
+pub struct SearchResults {
+    pub query_id: u64,
+    pub results: Vec<SearchResult>,
+    /// Plugins the query was sent to that haven't finished yet, the results are complete once
+    /// it's empty
+    pub pending: Vec<String>,
+}
+
 pub struct ClientState {
-    search_query: String,
+    search_query: Query,
+    pending: BTreeSet<String>,
+    /// Every result returned for the query so far, in the order each plugin returned them
+    received: Vec<SearchResult>,
     search_results: Vec<SearchResult>,
+    /// Whether results of the query were sent to subscribers yet
+    published: bool,
+    usage: ranking::UsageStats,
+    subscribers: Vec<mpsc::Sender<SearchResults>>,
 }
 
 impl Default for ClientState {
     fn default() -> Self {
         Self {
-            search_query: String::new(),
+            search_query: Query::default(),
+            pending: BTreeSet::new(),
+            received: vec![],
             search_results: vec![],
+            published: false,
+            usage: ranking::UsageStats::default(),
+            subscribers: vec![],
         }
     }
 }
 
+enum WorkerMessage {
+    Query(Query),
+    ConfigChanged(config::Config),
+    UsageRecorded(SearchResult),
+    Callback { id: String, payload: String },
+    Stop,
+}
+
+/// How long the first results of a query are held back for plugins that haven't answered, so
+/// rows don't jump around right after they're shown
+const FIRST_RESULTS_DEADLINE: time::Duration = time::Duration::from_millis(100);
+/// Delay before the first restart of a crashed plugin, doubled on every consecutive crash
+const INITIAL_BACKOFF: time::Duration = time::Duration::from_secs(1);
+const MAX_BACKOFF: time::Duration = time::Duration::from_secs(60);
+/// A plugin running this long without crashing restarts with the initial backoff again
+const STABLE_AFTER: time::Duration = time::Duration::from_secs(60);
+
+/// Locks the mutex, recovering it if a plugin panicked while holding it instead of taking down
+/// every other plugin and command that shares it
+pub fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|error| {
+        mutex.clear_poison();
+        error.into_inner()
+    })
+}
+
+/// Runs a call into plugin code, turning a panic into its message
+fn catch_panic<T>(call: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(call)).map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string())
+    })
+}
+
 struct Worker {
-    id: usize,
     plugin_name: String,
+    sender: mpsc::Sender<WorkerMessage>,
     thread: std::thread::JoinHandle<()>,
 }
 
+impl Worker {
+    /// Spawns a thread that runs fresh instances of the plugin until stopped, restarting the
+    /// plugin with an increasing delay whenever it panics
+    fn spawn(
+        plugin_name: String,
+        loaded_plugin: Arc<Mutex<LoadedPlugin>>,
+        client_state: Arc<Mutex<ClientState>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel::<WorkerMessage>();
+        let thread_plugin_name = plugin_name.clone();
+        let thread = std::thread::spawn(move || {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let plugin = lock(&loaded_plugin).plugin.clone();
+                let started = time::Instant::now();
+                let message = match Self::run(
+                    &thread_plugin_name,
+                    plugin,
+                    &loaded_plugin,
+                    &client_state,
+                    &receiver,
+                ) {
+                    Ok(()) => break,
+                    Err(message) => message,
+                };
+
+                if started.elapsed() > STABLE_AFTER {
+                    backoff = INITIAL_BACKOFF;
+                }
+                println!(
+                    "Plugin {} panicked, restarting in {}s: {}",
+                    thread_plugin_name,
+                    backoff.as_secs(),
+                    message
+                );
+                {
+                    let mut loaded_plugin = lock(&loaded_plugin);
+                    loaded_plugin.crashes += 1;
+                    loaded_plugin.status = PluginStatus::Crashed {
+                        message,
+                        restart_in: backoff.as_secs(),
+                    };
+                }
+                // the query it crashed on won't be answered
+                let query_id = lock(&client_state).get_search_query().id;
+                Self::skip_query(&thread_plugin_name, &client_state, query_id);
+                if Self::wait_for_stop(&thread_plugin_name, &client_state, &receiver, backoff) {
+                    break;
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+        Self {
+            plugin_name,
+            sender,
+            thread,
+        }
+    }
+
+    /// Initialises the plugin and serves queries until stopped. Returns the panic message if
+    /// the plugin panicked, the instance is dropped without `destroy` in that case.
+    fn run(
+        plugin_name: &str,
+        mut plugin: Box<dyn Plugin>,
+        loaded_plugin: &Arc<Mutex<LoadedPlugin>>,
+        client_state: &Arc<Mutex<ClientState>>,
+        receiver: &mpsc::Receiver<WorkerMessage>,
+    ) -> std::result::Result<(), String> {
+        let set_status = |status: PluginStatus| {
+            lock(loaded_plugin).status = status;
+        };
+        if let Err(error) = catch_panic(|| plugin.init(Arc::clone(loaded_plugin)))? {
+            println!("Plugin {} failed to initialize: {}", plugin_name, error);
+            set_status(PluginStatus::Failed {
+                message: error.to_string(),
+            });
+            // keep the channel open so queries are dropped quietly until the plugin is stopped
+            Self::wait_for_stop(plugin_name, client_state, receiver, time::Duration::MAX);
+            return Ok(());
+        }
+        set_status(PluginStatus::Healthy);
+        println!("Plugin {} initialized!", plugin_name);
+        // the task that degraded the plugin, only that task succeeding again clears it
+        let mut failed_task = None;
+        let mut report = |task: &'static str, result: Result<()>| match result {
+            Ok(()) => {
+                if failed_task == Some(task) {
+                    failed_task = None;
+                    set_status(PluginStatus::Healthy);
+                }
+            }
+            Err(error) => {
+                failed_task = Some(task);
+                println!("Plugin {} failed to {}: {}", plugin_name, task, error);
+                set_status(PluginStatus::Degraded {
+                    message: error.to_string(),
+                });
+            }
+        };
+        let destroy = |plugin: &mut Box<dyn Plugin>| {
+            if let Err(message) = catch_panic(|| plugin.destroy()) {
+                println!(
+                    "Plugin {} panicked while stopping: {}",
+                    plugin_name, message
+                );
+            }
+        };
+        loop {
+            match receiver.recv_timeout(time::Duration::from_millis(100)) {
+                Ok(WorkerMessage::Query(mut query)) => {
+                    // only the latest query matters, skip the ones that queued up behind it
+                    let mut stop = false;
+                    while let Ok(message) = receiver.try_recv() {
+                        match message {
+                            WorkerMessage::Query(next_query) => query = next_query,
+                            WorkerMessage::ConfigChanged(config) => report(
+                                "update its config",
+                                catch_panic(|| plugin.update_config(&config))?,
+                            ),
+                            WorkerMessage::UsageRecorded(result) => report(
+                                "record usage",
+                                catch_panic(|| plugin.record_usage(&result))?,
+                            ),
+                            WorkerMessage::Callback { id, payload } => report(
+                                "handle a callback",
+                                catch_panic(|| plugin.handle_callback(&id, &payload))?,
+                            ),
+                            WorkerMessage::Stop => stop = true,
+                        }
+                    }
+                    if stop {
+                        destroy(&mut plugin);
+                        return Ok(());
+                    }
+                    let batch = ResultBatch {
+                        query_id: query.id,
+                        plugin: plugin_name.to_string(),
+                        results: catch_panic(|| plugin.search(&query))?,
+                        finished: true,
+                    };
+                    lock(client_state).add_search_results(batch);
+                }
+                Ok(WorkerMessage::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    destroy(&mut plugin);
+                    return Ok(());
+                }
+                Ok(WorkerMessage::ConfigChanged(config)) => report(
+                    "update its config",
+                    catch_panic(|| plugin.update_config(&config))?,
+                ),
+                Ok(WorkerMessage::UsageRecorded(result)) => report(
+                    "record usage",
+                    catch_panic(|| plugin.record_usage(&result))?,
+                ),
+                Ok(WorkerMessage::Callback { id, payload }) => report(
+                    "handle a callback",
+                    catch_panic(|| plugin.handle_callback(&id, &payload))?,
+                ),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    report("run", catch_panic(|| plugin.start())?)
+                }
+            }
+        }
+    }
+
+    /// Skips incoming queries for up to `timeout`, returning whether the worker was stopped
+    fn wait_for_stop(
+        plugin_name: &str,
+        client_state: &Arc<Mutex<ClientState>>,
+        receiver: &mpsc::Receiver<WorkerMessage>,
+        timeout: time::Duration,
+    ) -> bool {
+        let deadline = time::Instant::now().checked_add(timeout);
+        loop {
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(time::Instant::now()),
+                None => time::Duration::MAX,
+            };
+            match receiver.recv_timeout(remaining) {
+                Ok(WorkerMessage::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => return true,
+                Ok(WorkerMessage::Query(query)) => {
+                    Self::skip_query(plugin_name, client_state, query.id)
+                }
+                Ok(_) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => return false,
+            }
+        }
+    }
+
+    /// Marks the plugin finished without results, so the query doesn't wait for it
+    fn skip_query(plugin_name: &str, client_state: &Arc<Mutex<ClientState>>, query_id: u64) {
+        lock(client_state).add_search_results(ResultBatch {
+            query_id,
+            plugin: plugin_name.to_string(),
+            results: vec![],
+            finished: true,
+        });
+    }
+
+    fn send_query(&self, query: Query) {
+        if self.sender.send(WorkerMessage::Query(query)).is_err() {
+            println!("Plugin {} is not accepting queries", self.plugin_name);
+        }
+    }
+
+    fn send_config(&self, config: config::Config) {
+        let _ = self.sender.send(WorkerMessage::ConfigChanged(config));
+    }
+
+    fn send_usage(&self, result: SearchResult) {
+        let _ = self.sender.send(WorkerMessage::UsageRecorded(result));
+    }
+
+    fn send_callback(&self, id: &str, payload: &str) -> bool {
+        self.sender
+            .send(WorkerMessage::Callback {
+                id: id.to_string(),
+                payload: payload.to_string(),
+            })
+            .is_ok()
+    }
+
+    fn request_stop(&self) {
+        let _ = self.sender.send(WorkerMessage::Stop);
+    }
+
+    /// Asks the worker to destroy its plugin and waits for the thread to finish
+    fn stop(self) {
+        self.request_stop();
+        self.join();
+    }
+
+    fn join(self) {
+        if self.thread.join().is_err() {
+            println!("Plugin {} worker panicked while stopping", self.plugin_name);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct LoadedPlugin {
+    /// Uninitialised copy of the plugin, cloned whenever a fresh instance has to be started
     pub plugin: Box<dyn Plugin>,
     pub metadata: Metadata,
-    pub config: HashMap<String, String>,
+    pub config: config::Config,
+    pub schema: config::ConfigSchema,
+    pub enabled: bool,
+    pub status: PluginStatus,
+    /// Number of times the plugin panicked since fin started
+    pub crashes: u32,
+    /// Runtime shared by every plugin for async work such as database queries, use
+    /// `runtime.block_on` instead of creating a runtime per call
+    pub runtime: tokio::runtime::Handle,
 }
 
 #[derive(Clone, serde::Serialize, Debug)]
 pub struct PluginData {
     pub metadata: Metadata,
-    pub config: HashMap<String, String>,
+    pub config: config::Config,
+    pub schema: config::ConfigSchema,
+    pub enabled: bool,
+    pub status: PluginStatus,
+    pub crashes: u32,
 }
 
+/// Owns the plugins and their workers. The plugin set is fixed once `init` ran, so the manager can
+/// be shared as is; every lock it takes internally is only held for bookkeeping, never while a
+/// plugin computes results.
 pub struct PluginManager {
     plugins: HashMap<String, Arc<Mutex<LoadedPlugin>>>,
     client_state: Arc<Mutex<ClientState>>,
-    workers: Vec<Worker>,
+    workers: Mutex<HashMap<String, Worker>>,
+    runtime: tokio::runtime::Handle,
+    /// Set when the manager created its own runtime instead of being handed one
+    owned_runtime: Option<tokio::runtime::Runtime>,
+    // declared last so third party libraries are unloaded after the plugins using them
+    libraries: Vec<libloading::Library>,
 }
 
 impl Default for PluginManager {
     fn default() -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("fin-plugins")
+            .enable_all()
+            .build()
+            .expect("Failed to create Tokio runtime");
+        let mut plugin_manager = Self::with_runtime(runtime.handle().clone());
+        plugin_manager.owned_runtime = Some(runtime);
+        plugin_manager
+    }
+}
+
+impl Drop for PluginManager {
+    /// Stops every worker before the libraries their plugins run code from are unloaded
+    fn drop(&mut self) {
+        let workers: Vec<Worker> = lock(&self.workers)
+            .drain()
+            .map(|(_, worker)| worker)
+            .collect();
+        // all asked first so the plugins are destroyed in parallel
+        for worker in &workers {
+            worker.request_stop();
+        }
+        for worker in workers {
+            worker.join();
+        }
+    }
+}
+
+impl PluginManager {
+    /// Creates a manager whose plugins run their async work on the given runtime
+    pub fn with_runtime(runtime: tokio::runtime::Handle) -> Self {
         Self {
             plugins: HashMap::new(),
             client_state: Arc::new(Mutex::new(ClientState::default())),
-            workers: vec![],
+            workers: Mutex::new(HashMap::new()),
+            runtime,
+            owned_runtime: None,
+            libraries: vec![],
         }
     }
-}
 
-impl PluginManager {
-    // pub fn init(&self, plugin_directory: &str) {}
-    // pub fn index_third_party_plugins(plugin_directory: &str) {
-    //     for entry in walkdir::WalkDir::new(plugin_directory)
-    //         .min_depth(1)
-    //         .into_iter()
-    //         .filter_entry(|entry| {
-    //             let file_name_substrings: Vec<String> = entry
-    //                 .file_name()
-    //                 .to_string_lossy()
-    //                 .split(".")
-    //                 .into_iter()
-    //                 .map(|substring| substring.to_string())
-    //                 .collect();
-    //             let file_extension = file_name_substrings
-    //                 .get(file_name_substrings.len() - 1)
-    //                 .unwrap();
-    //             file_extension == "so" || file_extension == "lua"
-    //         }borrow)
-    //         .filter_map(Result::ok)
-    //     {
-    //         dbg!(entry.path());
-    //     }
-    // }
-    pub fn init(&mut self, plugins: Vec<Box<dyn Plugin>>) {
-        for mut plugin in plugins {
-            let metadata = plugin.get_metadata();
+    /// Loads every shared library and Lua plugin found in the plugin directory. Successfully
+    /// loaded plugins still have to be passed to `init`.
+    pub fn load_third_party_plugins(
+        &mut self,
+        plugin_directory: &Path,
+    ) -> Vec<Result<Box<dyn Plugin>, loader::LoadError>> {
+        loader::find_plugins(plugin_directory)
+            .iter()
+            .map(|path| {
+                let plugin: Box<dyn Plugin> = if loader::is_lua_plugin(path) {
+                    Box::new(lua::LuaPlugin::load(path).map_err(|error| {
+                        loader::LoadError::Script {
+                            path: path.to_path_buf(),
+                            error,
+                        }
+                    })?)
+                } else {
+                    let external_plugin = loader::load_plugin(path)?;
+                    self.libraries.push(external_plugin.library);
+                    external_plugin.plugin
+                };
+                println!("Loaded third party plugin from {}", path.display());
+                Ok(plugin)
+            })
+            .collect()
+    }
+
+    /// Registers and starts the plugins, applying any stored config values on top of the defaults
+    pub fn init(
+        &mut self,
+        plugins: Vec<Box<dyn Plugin>>,
+        configs: &HashMap<String, config::Config>,
+    ) {
+        for plugin in plugins {
+            // without a name the plugin can't even be listed as failed
+            let metadata = match catch_panic(|| plugin.get_metadata()) {
+                Ok(metadata) => metadata,
+                Err(message) => {
+                    println!(
+                        "Skipping a plugin that panicked describing itself: {}",
+                        message
+                    );
+                    continue;
+                }
+            };
+            let (schema, status) = match catch_panic(|| plugin.get_config_schema()) {
+                Ok(schema) => (schema, PluginStatus::Healthy),
+                Err(message) => {
+                    println!(
+                        "Plugin {} panicked declaring its config: {}",
+                        metadata.name, message
+                    );
+                    (vec![], PluginStatus::Failed { message })
+                }
+            };
+            let failed = matches!(status, PluginStatus::Failed { .. });
             let loaded_plugin = LoadedPlugin {
                 metadata: metadata.clone(),
-                plugin: plugin.clone(),
-                config: plugin.get_config(),
+                config: Self::resolve_config(&metadata.name, &schema, configs.get(&metadata.name)),
+                schema,
+                plugin,
+                enabled: true,
+                status,
+                crashes: 0,
+                runtime: self.runtime.clone(),
             };
             self.plugins
                 .insert(metadata.name.clone(), Arc::new(Mutex::new(loaded_plugin)));
-            plugin.init(
-                Arc::clone(&self.client_state),
-                Arc::clone(
-                    &self
-                        .plugins
-                        .get(&metadata.name)
-                        .expect("Plugin should exist in plugin manager state"),
-                ),
-            );
-            self.workers.push(Worker {
-                id: 0,
-                plugin_name: metadata.name.clone(),
-                thread: std::thread::spawn(move || loop {
-                    plugin.start();
-                    std::thread::sleep(time::Duration::from_millis(100));
-                }),
-            });
+            // stays down until it's reloaded, like a plugin whose `init` failed
+            if !failed {
+                let worker = self.spawn_worker(&metadata.name);
+                lock(&self.workers).insert(metadata.name.clone(), worker);
+            }
+        }
+    }
 
-            println!("Plugin {} initialized!", metadata.name);
+    /// Overlays the stored values that pass validation on top of the schema defaults
+    fn resolve_config(
+        name: &str,
+        schema: &[config::ConfigField],
+        stored: Option<&config::Config>,
+    ) -> config::Config {
+        let mut resolved = config::defaults(schema);
+        for (key, value) in stored.into_iter().flatten() {
+            match schema.iter().find(|field| &field.key == key) {
+                Some(field) => match field.validate(value) {
+                    Ok(()) => {
+                        resolved.insert(key.clone(), value.clone());
+                    }
+                    Err(error) => println!("Ignoring config for {}: {}", name, error),
+                },
+                None => println!("Ignoring unknown config key {}.{}", name, key),
+            }
         }
+        resolved
+    }
+
+    /// Replaces every plugin's config with the given stored values, e.g. after fin.toml changed
+    pub fn apply_plugin_configs(&self, configs: &HashMap<String, config::Config>) {
+        for (name, loaded_plugin_arc) in self.plugins.iter() {
+            let mut loaded_plugin = lock(loaded_plugin_arc);
+            let resolved = Self::resolve_config(name, &loaded_plugin.schema, configs.get(name));
+            if resolved != loaded_plugin.config {
+                loaded_plugin.config = resolved.clone();
+                if let Some(worker) = lock(&self.workers).get(name) {
+                    worker.send_config(resolved);
+                }
+            }
+        }
+    }
+
+    /// Spawns a worker thread running a fresh instance of the plugin
+    fn spawn_worker(&self, name: &str) -> Worker {
+        let loaded_plugin_arc = Arc::clone(
+            self.plugins
+                .get(name)
+                .expect("Plugin should exist in plugin manager state"),
+        );
+        Worker::spawn(
+            name.to_string(),
+            loaded_plugin_arc,
+            Arc::clone(&self.client_state),
+        )
+    }
+
+    fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), String> {
+        let loaded_plugin_arc = self
+            .plugins
+            .get(name)
+            .ok_or(format!("Plugin {} not found", name))?;
+        lock(loaded_plugin_arc).enabled = enabled;
+        Ok(())
+    }
+
+    pub fn enable_plugin(&self, name: &str) -> Result<(), String> {
+        self.set_enabled(name, true)?;
+        lock(&self.workers)
+            .entry(name.to_string())
+            .or_insert_with(|| self.spawn_worker(name));
+        Ok(())
+    }
+
+    /// Stops the plugin's worker, waiting for it to finish its current call and `destroy` itself.
+    /// Blocks for as long as that takes, but other plugins keep serving queries meanwhile.
+    pub fn disable_plugin(&self, name: &str) -> Result<(), String> {
+        self.set_enabled(name, false)?;
+        // taken out of the map first so the wait doesn't hold up queries to other plugins
+        let worker = lock(&self.workers).remove(name);
+        if let Some(worker) = worker {
+            worker.stop();
+            println!("Plugin {} disabled!", name);
+        }
+        Ok(())
+    }
+
+    /// Replaces the running instance of the plugin with a freshly initialised one
+    /// Restarts the plugin from a reloaded instance, picking up e.g. edits to a script. A plugin
+    /// that fails to reload is marked as failed and stays down until it's reloaded again.
+    pub fn reload_plugin(&self, name: &str) -> Result<(), String> {
+        self.set_enabled(name, true)?;
+        let worker = lock(&self.workers).remove(name);
+        if let Some(worker) = worker {
+            worker.stop();
+        }
+        {
+            let mut loaded_plugin = lock(&self.plugins[name]);
+            let reloaded = catch_panic(|| loaded_plugin.plugin.reload())
+                .and_then(|result| result.map_err(|error| error.to_string()))
+                .and_then(|()| catch_panic(|| loaded_plugin.plugin.get_config_schema()));
+            match reloaded {
+                Ok(schema) => {
+                    // stored values the new schema no longer accepts fall back to the defaults
+                    loaded_plugin.config =
+                        Self::resolve_config(name, &schema, Some(&loaded_plugin.config));
+                    loaded_plugin.schema = schema;
+                }
+                Err(message) => {
+                    println!("Plugin {} failed to reload: {}", name, message);
+                    loaded_plugin.status = PluginStatus::Failed { message };
+                    return Ok(());
+                }
+            }
+        }
+        lock(&self.workers).insert(name.to_string(), self.spawn_worker(name));
+        Ok(())
     }
 
     pub fn get_plugins(&self) -> HashMap<String, PluginData> {
         let mut loaded_plugins = HashMap::new();
         for (name, loaded_plugin_arc) in self.plugins.iter() {
-            let loaded_plugin = loaded_plugin_arc
-                .lock()
-                .expect("Plugin mutex should not be poisoned");
+            let loaded_plugin = lock(loaded_plugin_arc);
             loaded_plugins.insert(
                 name.clone(),
                 PluginData {
                     metadata: loaded_plugin.metadata.clone(),
                     config: loaded_plugin.config.clone(),
+                    schema: loaded_plugin.schema.clone(),
+                    enabled: loaded_plugin.enabled,
+                    status: loaded_plugin.status.clone(),
+                    crashes: loaded_plugin.crashes,
                 },
             );
         }
@@ -202,32 +883,203 @@ impl PluginManager {
         self.plugins.get_mut(name)
     }
 
-    pub fn get_client_state(&self) -> MutexGuard<ClientState> {
-        loop {
-            let client_state_guard = self.client_state.try_lock();
-            if client_state_guard.is_ok() {
-                return client_state_guard.expect("Thread should not be poisoned");
-            }
+    /// Validates the value against the plugin's config schema before storing it
+    pub fn update_plugin_config(
+        &self,
+        name: &str,
+        key: &str,
+        value: config::ConfigValue,
+    ) -> Result<(), config::ConfigError> {
+        let loaded_plugin_arc =
+            self.plugins
+                .get(name)
+                .ok_or_else(|| config::ConfigError::UnknownPlugin {
+                    plugin: name.to_string(),
+                })?;
+        let mut loaded_plugin = lock(loaded_plugin_arc);
+        let field = loaded_plugin
+            .schema
+            .iter()
+            .find(|field| field.key == key)
+            .ok_or_else(|| config::ConfigError::UnknownKey {
+                plugin: name.to_string(),
+                key: key.to_string(),
+            })?;
+        field.validate(&value)?;
+        loaded_plugin.config.insert(key.to_string(), value);
+        if let Some(worker) = lock(&self.workers).get(name) {
+            worker.send_config(loaded_plugin.config.clone());
         }
+        Ok(())
+    }
+
+    pub fn get_client_state(&self) -> MutexGuard<'_, ClientState> {
+        lock(&self.client_state)
     }
     pub fn get_client_state_arc(&self) -> Arc<Mutex<ClientState>> {
         Arc::clone(&self.client_state)
     }
+
+    /// Starts a new query generation and dispatches it to every plugin, returning its id.
+    /// Empty queries only clear the current results.
+    pub fn update_search_query(&self, query: String) -> u64 {
+        let mut client_state = self.get_client_state();
+        if let Some(query) = client_state
+            .update_search_query(query)
+            .filter(|query| !query.text.is_empty())
+        {
+            let workers = lock(&self.workers);
+            client_state.expect_results(workers.keys().cloned());
+            for worker in workers.values() {
+                worker.send_query(query.clone());
+            }
+            let client_state = self.get_client_state_arc();
+            self.runtime.spawn(async move {
+                tokio::time::sleep(FIRST_RESULTS_DEADLINE).await;
+                lock(&client_state).publish_held(query.id);
+            });
+        }
+        client_state.get_search_query().id
+    }
+
+    /// Remembers that a result was picked, ranking it higher in later queries, and lets the
+    /// plugin that returned it know
+    pub fn record_usage(&self, result: &SearchResult) {
+        self.get_client_state().record_usage(result);
+        if let Some(name) = result.plugin() {
+            if let Some(worker) = lock(&self.workers).get(name) {
+                worker.send_usage(result.clone());
+            }
+        }
+    }
+
+    /// Looks up the action the user picked in the results listed for the query, recording the
+    /// result's usage. Only results the plugins returned can be picked, failing if the query has
+    /// been replaced since.
+    pub fn pick_action(
+        &self,
+        query_id: u64,
+        result_index: usize,
+        action_index: usize,
+    ) -> Result<Action, executor::ActionError> {
+        let result = {
+            let client_state = self.get_client_state();
+            if client_state.search_query.id != query_id {
+                return Err(executor::ActionError::StaleResult);
+            }
+            client_state.search_results.get(result_index).cloned()
+        }
+        .ok_or(executor::ActionError::StaleResult)?;
+        let action = result
+            .actions()
+            .get(action_index)
+            .map(|result_action| result_action.action.clone())
+            .ok_or(executor::ActionError::StaleResult)?;
+        self.record_usage(&result);
+        Ok(action)
+    }
+
+    /// Hands an `Action::PluginCallback` to the plugin it's meant for, failing if the plugin
+    /// isn't running
+    pub fn send_callback(
+        &self,
+        plugin: &str,
+        id: &str,
+        payload: &str,
+    ) -> Result<(), executor::ActionError> {
+        // a failed or crashed plugin's worker drops messages until it's reloaded or restarted
+        let running = self.plugins.get(plugin).is_some_and(|loaded_plugin| {
+            matches!(
+                lock(loaded_plugin).status,
+                PluginStatus::Healthy | PluginStatus::Degraded { .. }
+            )
+        });
+        match lock(&self.workers).get(plugin) {
+            Some(worker) if running && worker.send_callback(id, payload) => Ok(()),
+            _ => Err(executor::ActionError::PluginNotRunning {
+                plugin: plugin.to_string(),
+            }),
+        }
+    }
+
+    /// Returns a receiver that gets the merged results whenever a plugin adds to them
+    pub fn subscribe(&self) -> mpsc::Receiver<SearchResults> {
+        let (sender, receiver) = mpsc::channel();
+        self.get_client_state().subscribe(sender);
+        receiver
+    }
     // fn load_plugin(&mut self, plugin_name: &str, plugin: Box<dyn Plugin>) {}
     // pub fn register_plugin(&mut self, plugin_name: &str, plugin: Box<dyn Plugin>) {}
 }
 
 impl ClientState {
-    pub fn update_search_query(&mut self, query: String) {
-        if self.search_query != query {
-            self.search_query = query;
-            self.search_results.clear();
+    /// Returns the new query if it differs from the current one, bumping the generation id
+    pub fn update_search_query(&mut self, query: String) -> Option<Query> {
+        if self.search_query.text == query {
+            return None;
+        }
+        self.search_query = Query {
+            id: self.search_query.id + 1,
+            text: query,
+        };
+        self.pending.clear();
+        self.received.clear();
+        self.search_results.clear();
+        self.published = false;
+        Some(self.search_query.clone())
+    }
+    /// Sets the plugins the current query was sent to, publishing the empty results right away if
+    /// there are none
+    pub fn expect_results(&mut self, plugins: impl IntoIterator<Item = String>) {
+        self.pending = plugins.into_iter().collect();
+        if self.pending.is_empty() {
+            self.publish();
+        }
+    }
+    /// Ranks a batch together with the results received before and sends them to subscribers
+    /// again, dropping the batch if the query is stale. The first results are held back until
+    /// every plugin answered or `FIRST_RESULTS_DEADLINE` passed.
+    pub fn add_search_results(&mut self, batch: ResultBatch) -> bool {
+        if batch.query_id != self.search_query.id {
+            return false;
+        }
+        if batch.finished {
+            self.pending.remove(&batch.plugin);
         }
+        self.received
+            .extend(batch.results.into_iter().map(|mut result| {
+                result.plugin = Some(batch.plugin.clone());
+                result
+            }));
+        self.search_results = ranking::rank(&self.search_query.text, &self.received, &self.usage);
+        if self.published || self.pending.is_empty() {
+            self.publish();
+        }
+        true
+    }
+    /// Sends the results of the query if they were held back waiting for slow plugins
+    fn publish_held(&mut self, query_id: u64) {
+        if query_id == self.search_query.id && !self.published {
+            self.publish();
+        }
+    }
+    fn publish(&mut self) {
+        self.published = true;
+        let snapshot = SearchResults {
+            query_id: self.search_query.id,
+            results: self.search_results.clone(),
+            pending: self.pending.iter().cloned().collect(),
+        };
+        self.subscribers
+            .retain(|subscriber| subscriber.send(snapshot.clone()).is_ok());
+    }
+    pub fn record_usage(&mut self, result: &SearchResult) {
+        self.usage.record(result);
     }
-    pub fn update_search_results(&mut self, results: Vec<SearchResult>) {
-        self.search_results = results;
+    pub fn subscribe(&mut self, subscriber: mpsc::Sender<SearchResults>) {
+        self.subscribers.push(subscriber);
     }
-    pub fn get_search_query(&self) -> &str {
+    pub fn get_search_query(&self) -> &Query {
         &self.search_query
     }
     pub fn get_search_results(&self) -> Vec<SearchResult> {
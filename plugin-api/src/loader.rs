@@ -0,0 +1,226 @@
+use crate::Plugin;
+use std::{
+    ffi::{c_char, CStr},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Bumped whenever the `Plugin` trait or any type crossing the plugin boundary changes.
+/// Shared libraries reporting a different version are refused instead of being called into.
+pub const ABI_VERSION: u32 = 7;
+
+/// The `plugin-api` version and the compiler a library was built with. Trait objects only cross
+/// the boundary safely if both match fin's exactly, the ABI version alone doesn't guarantee that.
+pub const BUILD_ID: &CStr = match CStr::from_bytes_with_nul(
+    concat!(
+        "plugin-api ",
+        env!("CARGO_PKG_VERSION"),
+        ", ",
+        env!("FIN_RUSTC_VERSION"),
+        "\0"
+    )
+    .as_bytes(),
+) {
+    Ok(build_id) => build_id,
+    Err(_) => panic!("build id contains a nul byte"),
+};
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"fin_plugin_abi_version";
+pub const BUILD_ID_SYMBOL: &[u8] = b"fin_plugin_build_id";
+pub const CREATE_SYMBOL: &[u8] = b"fin_plugin_create";
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type BuildIdFn = unsafe extern "C" fn() -> *const c_char;
+type CreateFn = unsafe extern "C" fn() -> *mut Box<dyn Plugin>;
+
+/// Exports the entry points `PluginManager` looks for in a third party plugin library.
+///
+/// ```ignore
+/// plugin_api::declare_plugin!(MyPlugin::default);
+/// ```
+#[macro_export]
+macro_rules! declare_plugin {
+    ($constructor:path) => {
+        #[no_mangle]
+        pub extern "C" fn fin_plugin_abi_version() -> u32 {
+            $crate::loader::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn fin_plugin_build_id() -> *const ::std::ffi::c_char {
+            $crate::loader::BUILD_ID.as_ptr()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn fin_plugin_create() -> *mut Box<dyn $crate::Plugin> {
+            let plugin: Box<dyn $crate::Plugin> = Box::new($constructor());
+            Box::into_raw(Box::new(plugin))
+        }
+    };
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Library {
+        path: PathBuf,
+        error: libloading::Error,
+    },
+    MissingSymbol {
+        path: PathBuf,
+        symbol: String,
+    },
+    AbiMismatch {
+        path: PathBuf,
+        expected: u32,
+        found: u32,
+    },
+    BuildMismatch {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    NullPlugin {
+        path: PathBuf,
+    },
+    Script {
+        path: PathBuf,
+        error: mlua::Error,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Library { path, error } => {
+                write!(f, "failed to open {}: {}", path.display(), error)
+            }
+            Self::MissingSymbol { path, symbol } => {
+                write!(f, "{} does not export `{}`", path.display(), symbol)
+            }
+            Self::AbiMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} was built against plugin ABI version {} but fin expects version {}",
+                path.display(),
+                found,
+                expected
+            ),
+            Self::BuildMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} was built with {} but fin was built with {}, rebuild it against the same plugin-api and toolchain",
+                path.display(),
+                found,
+                expected
+            ),
+            Self::NullPlugin { path } => {
+                write!(f, "{} returned a null plugin", path.display())
+            }
+            Self::Script { path, error } => {
+                write!(f, "failed to load {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// A plugin instantiated from a shared library, along with the library backing its code.
+/// The library has to outlive every clone of the plugin.
+pub struct ExternalPlugin {
+    pub plugin: Box<dyn Plugin>,
+    pub library: libloading::Library,
+}
+
+pub fn default_plugin_directory() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|base_dirs| base_dirs.home_dir().join(".config/fin/plugins"))
+}
+
+pub fn is_lua_plugin(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension == "lua")
+        .unwrap_or(false)
+}
+
+/// Lists the shared libraries and Lua scripts directly inside the plugin directory
+pub fn find_plugins(plugin_directory: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(plugin_directory)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            is_lua_plugin(path)
+                || path
+                    .extension()
+                    .map(|extension| extension == std::env::consts::DLL_EXTENSION)
+                    .unwrap_or(false)
+        })
+        .collect()
+}
+
+pub fn load_plugin(path: &Path) -> Result<ExternalPlugin, LoadError> {
+    // SAFETY: loading runs the library's initialisers, plugins in the plugin directory are trusted
+    let library =
+        unsafe { libloading::Library::new(path) }.map_err(|error| LoadError::Library {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+    let missing_symbol = |symbol: &[u8]| LoadError::MissingSymbol {
+        path: path.to_path_buf(),
+        symbol: String::from_utf8_lossy(symbol).to_string(),
+    };
+
+    // SAFETY: the symbol types match the signatures exported by `declare_plugin!`, and the ABI
+    // version and build id, which only cross the boundary as C types, are checked before
+    // anything else in the library is called
+    unsafe {
+        let abi_version = library
+            .get::<AbiVersionFn>(ABI_VERSION_SYMBOL)
+            .map_err(|_| missing_symbol(ABI_VERSION_SYMBOL))?;
+        let found = abi_version();
+        if found != ABI_VERSION {
+            return Err(LoadError::AbiMismatch {
+                path: path.to_path_buf(),
+                expected: ABI_VERSION,
+                found,
+            });
+        }
+
+        let build_id = library
+            .get::<BuildIdFn>(BUILD_ID_SYMBOL)
+            .map_err(|_| missing_symbol(BUILD_ID_SYMBOL))?;
+        let found = CStr::from_ptr(build_id());
+        if found != BUILD_ID {
+            return Err(LoadError::BuildMismatch {
+                path: path.to_path_buf(),
+                expected: BUILD_ID.to_string_lossy().to_string(),
+                found: found.to_string_lossy().to_string(),
+            });
+        }
+
+        let create = library
+            .get::<CreateFn>(CREATE_SYMBOL)
+            .map_err(|_| missing_symbol(CREATE_SYMBOL))?;
+        let plugin = create();
+        if plugin.is_null() {
+            return Err(LoadError::NullPlugin {
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(ExternalPlugin {
+            plugin: *Box::from_raw(plugin),
+            library,
+        })
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    config::{self, ConfigField, ConfigKind, ConfigValue},
+    Action, Icon, LoadedPlugin, Metadata, Plugin, Query, Result, ResultAction, SearchResult,
+};
+use mlua::LuaSerdeExt;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+const MODULE_KEY: &str = "fin_plugin";
+
+/// Adapts a Lua script into a `Plugin`. The script returns a table describing the plugin:
+///
+/// ```lua
+/// return {
+///   metadata = { name = "Hello", description = "Greets you" },
+///   config = {
+///     greeting = "Hello",
+///     count = { type = "int", min = 1, default = 3, description = "How many greetings" },
+///   },
+///   search = function(query, config)
+///     return { { title = config.greeting .. " " .. query, icon = "Copy", action = { CopyText = query } } }
+///   end,
+/// }
+/// ```
+///
+/// Optional `init(config)`, `update_config(config)` and `destroy()` functions are called with the
+/// plugin lifecycle, `callback(id, payload)` when a result with a `PluginCallback` action for the
+/// plugin is picked. Results can list further `actions` with a `label`, an `action` and an
+/// optional `shortcut`, offered in the action panel of the selected result.
+pub struct LuaPlugin {
+    path: PathBuf,
+    /// The script as it was read when loading or reloading the plugin
+    source: Arc<str>,
+    /// The state the script runs in, each instance evaluates it afresh in `init`
+    lua: Option<Arc<Mutex<mlua::Lua>>>,
+    metadata: Metadata,
+    schema: config::ConfigSchema,
+    loaded_plugin: Option<Arc<Mutex<LoadedPlugin>>>,
+}
+
+/// A config entry is either a bare default value or a table describing the field
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum LuaConfigField {
+    Field {
+        #[serde(flatten)]
+        kind: ConfigKind,
+        default: ConfigValue,
+        #[serde(default)]
+        description: String,
+    },
+    Default(ConfigValue),
+}
+
+#[derive(serde::Deserialize)]
+struct LuaSearchResult {
+    title: String,
+    description: Option<String>,
+    icon: Option<Icon>,
+    action: Option<Action>,
+    /// Further actions, e.g. `{ label = "Copy", action = { CopyText = query }, shortcut = "Ctrl+C" }`
+    #[serde(default)]
+    actions: Vec<ResultAction>,
+    priority: Option<u8>,
+    score: Option<f32>,
+}
+
+impl LuaPlugin {
+    pub fn load(path: &Path) -> mlua::Result<Self> {
+        let source: Arc<str> = std::fs::read_to_string(path)
+            .map_err(mlua::Error::external)?
+            .into();
+        let lua = mlua::Lua::new();
+        let module = Self::evaluate(&lua, path, &source)?;
+
+        let metadata_table: mlua::Table = module.get("metadata")?;
+        let metadata = Metadata {
+            name: metadata_table.get("name")?,
+            description: metadata_table
+                .get::<_, Option<String>>("description")?
+                .unwrap_or_default(),
+            icon: metadata_table.get("icon")?,
+            url: metadata_table.get("url")?,
+        };
+        let config: Option<HashMap<String, mlua::Value>> = module.get("config")?;
+        let mut schema = config
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| {
+                Ok(match lua.from_value::<LuaConfigField>(value)? {
+                    LuaConfigField::Field {
+                        kind,
+                        default,
+                        description,
+                    } => ConfigField::new(&key, kind, default, &description),
+                    LuaConfigField::Default(default) => {
+                        ConfigField::new(&key, ConfigKind::infer(&default), default, "")
+                    }
+                })
+            })
+            .collect::<mlua::Result<config::ConfigSchema>>()?;
+        schema.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            source,
+            lua: None,
+            metadata,
+            schema,
+            loaded_plugin: None,
+        })
+    }
+
+    /// Runs the script, returning the table it describes the plugin with
+    fn evaluate<'lua>(
+        lua: &'lua mlua::Lua,
+        path: &Path,
+        source: &str,
+    ) -> mlua::Result<mlua::Table<'lua>> {
+        let module: mlua::Table = lua.load(source).set_name(path.to_string_lossy()).eval()?;
+        // fail early instead of on the first query
+        if module.get::<_, Option<mlua::Function>>("search")?.is_none() {
+            return Err(mlua::Error::runtime(
+                "script does not export a `search` function",
+            ));
+        }
+        Ok(module)
+    }
+
+    fn lua(&self) -> mlua::Result<MutexGuard<'_, mlua::Lua>> {
+        self.lua
+            .as_deref()
+            .map(crate::lock)
+            .ok_or_else(|| mlua::Error::runtime("the plugin is not initialised"))
+    }
+
+    fn current_config(&self) -> config::Config {
+        match &self.loaded_plugin {
+            Some(loaded_plugin) => crate::lock(loaded_plugin).config.clone(),
+            None => config::defaults(&self.schema),
+        }
+    }
+
+    /// Calls an optional function exported by the script, passing it the config if given
+    fn call_hook(&self, name: &str, config: Option<config::Config>) -> mlua::Result<()> {
+        let lua = self.lua()?;
+        let module: mlua::Table = lua.named_registry_value(MODULE_KEY)?;
+        let hook: Option<mlua::Function> = module.get(name)?;
+        let result = match (hook, config) {
+            (Some(hook), Some(config)) => hook.call::<_, ()>(lua.to_value(&config)?),
+            (Some(hook), None) => hook.call::<_, ()>(()),
+            (None, _) => Ok(()),
+        };
+        result
+    }
+
+    /// Calls the optional `callback(id, payload)` function exported by the script
+    fn call_callback(&self, id: &str, payload: &str) -> mlua::Result<()> {
+        let lua = self.lua()?;
+        let module: mlua::Table = lua.named_registry_value(MODULE_KEY)?;
+        let callback: Option<mlua::Function> = module.get("callback")?;
+        match callback {
+            Some(callback) => callback.call::<_, ()>((id, payload)),
+            None => Ok(()),
+        }
+    }
+
+    fn call_search(&self, query: &str) -> mlua::Result<Vec<SearchResult>> {
+        let config = self.current_config();
+        let lua = self.lua()?;
+        let module: mlua::Table = lua.named_registry_value(MODULE_KEY)?;
+        let search: mlua::Function = module.get("search")?;
+        let results: mlua::Value = search.call((query, lua.to_value(&config)?))?;
+        let results: Vec<LuaSearchResult> = lua.from_value(results)?;
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                let search_result = SearchResult::new(
+                    result.title,
+                    result.description,
+                    result.icon,
+                    result.action,
+                    result.priority,
+                )
+                .with_actions(result.actions);
+                match result.score {
+                    Some(score) => search_result.with_score(score),
+                    None => search_result,
+                }
+            })
+            .collect())
+    }
+}
+
+impl Plugin for LuaPlugin {
+    fn init(&mut self, loaded_plugin: Arc<Mutex<LoadedPlugin>>) -> Result<()> {
+        let lua = mlua::Lua::new();
+        let module = Self::evaluate(&lua, &self.path, &self.source)?;
+        lua.set_named_registry_value(MODULE_KEY, module)?;
+        self.lua = Some(Arc::new(Mutex::new(lua)));
+        self.loaded_plugin = Some(loaded_plugin);
+        Ok(self.call_hook("init", Some(self.current_config()))?)
+    }
+
+    fn start(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn update_config(&mut self, config: &config::Config) -> Result<()> {
+        Ok(self.call_hook("update_config", Some(config.clone()))?)
+    }
+
+    fn search(&mut self, query: &Query) -> Vec<SearchResult> {
+        match self.call_search(&query.text) {
+            Ok(results) => results,
+            Err(error) => {
+                println!("{}: `search` failed: {}", self.path.display(), error);
+                vec![]
+            }
+        }
+    }
+
+    fn handle_callback(&mut self, id: &str, payload: &str) -> Result<()> {
+        Ok(self.call_callback(id, payload)?)
+    }
+
+    fn get_metadata(&self) -> Metadata {
+        self.metadata.clone()
+    }
+
+    fn get_config_schema(&self) -> config::ConfigSchema {
+        self.schema.clone()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        *self = Self::load(&self.path)?;
+        Ok(())
+    }
+
+    fn destroy(&mut self) {
+        // `init` failed, the script never ran
+        if self.lua.is_none() {
+            return;
+        }
+        if let Err(error) = self.call_hook("destroy", None) {
+            println!("{}: `destroy` failed: {}", self.path.display(), error);
+        }
+    }
+
+    /// A fresh instance of the script as it was loaded, it runs in a state of its own once
+    /// initialised and doesn't inherit the state of the instance it replaces
+    fn clone_box(&self) -> Box<dyn Plugin> {
+        Box::new(Self {
+            path: self.path.clone(),
+            source: Arc::clone(&self.source),
+            lua: None,
+            metadata: self.metadata.clone(),
+            schema: self.schema.clone(),
+            loaded_plugin: None,
+        })
+    }
+}
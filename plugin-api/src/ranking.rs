@@ -0,0 +1,200 @@
+use crate::{Action, SearchResult};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+/// Results kept from a single plugin, so one chatty plugin can't push everything else out
+pub const RESULTS_PER_PLUGIN: usize = 20;
+
+const MATCH_WEIGHT: f32 = 1.0;
+const PRIORITY_WEIGHT: f32 = 0.5;
+const FREQUENCY_WEIGHT: f32 = 0.3;
+const RECENCY_WEIGHT: f32 = 0.2;
+/// Time after which the recency boost of a used result has halved
+const RECENCY_HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug, Default)]
+struct Usage {
+    count: u32,
+    last_used: Option<SystemTime>,
+}
+
+/// How often and how recently results were picked, keyed by `SearchResult::usage_key`
+#[derive(Clone, Debug, Default)]
+pub struct UsageStats {
+    usage: HashMap<String, Usage>,
+}
+
+impl UsageStats {
+    pub fn record(&mut self, result: &SearchResult) {
+        let usage = self.usage.entry(result.usage_key()).or_default();
+        usage.count += 1;
+        usage.last_used = Some(SystemTime::now());
+    }
+
+    /// Frequency and recency boost in `0.0..=FREQUENCY_WEIGHT + RECENCY_WEIGHT`
+    fn score(&self, result: &SearchResult) -> f32 {
+        let Some(usage) = self.usage.get(&result.usage_key()) else {
+            return 0.0;
+        };
+        let frequency = usage.count as f32 / (usage.count as f32 + 3.0);
+        let recency = usage
+            .last_used
+            .and_then(|last_used| last_used.elapsed().ok())
+            .map(|age| 0.5_f32.powf(age.as_secs_f32() / RECENCY_HALF_LIFE.as_secs_f32()))
+            .unwrap_or(0.0);
+        FREQUENCY_WEIGHT * frequency + RECENCY_WEIGHT * recency
+    }
+}
+
+/// How well the title matches the query, in `0.0..=1.0`. Used for results whose plugin didn't
+/// provide a score of its own.
+pub fn match_quality(query: &str, title: &str) -> f32 {
+    let query = query.trim().to_lowercase();
+    let title = title.to_lowercase();
+    if query.is_empty() {
+        0.0
+    } else if title == query {
+        1.0
+    } else if title.starts_with(&query) {
+        0.8
+    } else if title
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(&query))
+    {
+        0.6
+    } else if title.contains(&query) {
+        0.4
+    } else {
+        // the plugin matched on something other than the title, e.g. a path
+        0.1
+    }
+}
+
+fn score(query: &str, result: &SearchResult, usage: &UsageStats) -> f32 {
+    let quality = result
+        .score
+        .map(|score| score.clamp(0.0, 1.0))
+        .unwrap_or_else(|| match_quality(query, &result.title));
+    let priority = result.priority.unwrap_or(0) as f32 / u8::MAX as f32;
+    MATCH_WEIGHT * quality + PRIORITY_WEIGHT * priority + usage.score(result)
+}
+
+/// Ranks the results every plugin returned for the query together. Ties are broken by plugin
+/// name and then by the plugin's own order, so the ranking doesn't depend on which plugin answered
+/// first. Results opening a target a higher ranked result opens already are dropped.
+pub fn rank(query: &str, results: &[SearchResult], usage: &UsageStats) -> Vec<SearchResult> {
+    let mut ranked: Vec<(f32, usize, &SearchResult)> = results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| (score(query, result, usage), index, result))
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| a.2.plugin().cmp(&b.2.plugin()))
+            .then_with(|| a.1.cmp(&b.1))
+    });
+
+    let mut targets: HashSet<&str> = HashSet::new();
+    let mut per_plugin: HashMap<Option<&str>, usize> = HashMap::new();
+    ranked
+        .into_iter()
+        .filter(|(_, _, result)| {
+            let unique = match result.action().and_then(Action::target) {
+                Some(target) => targets.insert(target),
+                None => true,
+            };
+            if !unique {
+                return false;
+            }
+            let count = per_plugin.entry(result.plugin()).or_default();
+            *count += 1;
+            *count <= RESULTS_PER_PLUGIN
+        })
+        .map(|(_, _, result)| result.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(plugin: &str, title: &str, score: f32) -> SearchResult {
+        let mut result =
+            SearchResult::new(title.to_string(), None, None, None, None).with_score(score);
+        result.plugin = Some(plugin.to_string());
+        result
+    }
+
+    fn titles(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.title.as_str()).collect()
+    }
+
+    #[test]
+    fn ranks_results_by_score() {
+        let results = [result("a", "low", 0.2), result("a", "high", 0.9)];
+        let ranked = rank("q", &results, &UsageStats::default());
+        assert_eq!(titles(&ranked), ["high", "low"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_plugin_name_then_plugin_order() {
+        let results = [
+            result("b", "b0", 0.5),
+            result("a", "a0", 0.5),
+            result("a", "a1", 0.5),
+        ];
+        let ranked = rank("q", &results, &UsageStats::default());
+        assert_eq!(titles(&ranked), ["a0", "a1", "b0"]);
+    }
+
+    #[test]
+    fn ranks_across_plugins_regardless_of_arrival() {
+        let usage = UsageStats::default();
+        let fast = result("fast", "fuzzy", 0.1);
+        let slow = result("slow", "exact", 1.0);
+        let ranked = rank("q", &[fast.clone(), slow.clone()], &usage);
+        assert_eq!(titles(&ranked), ["exact", "fuzzy"]);
+        assert_eq!(titles(&rank("q", &[slow, fast], &usage)), titles(&ranked));
+    }
+
+    #[test]
+    fn drops_results_opening_a_listed_target() {
+        let open = |plugin: &str, title: &str, score: f32| {
+            let mut result = SearchResult::new(
+                title.to_string(),
+                None,
+                None,
+                Some(Action::Open("/tmp/file".to_string())),
+                None,
+            )
+            .with_score(score);
+            result.plugin = Some(plugin.to_string());
+            result
+        };
+        let results = [open("a", "worse", 0.2), open("b", "better", 0.8)];
+        let ranked = rank("q", &results, &UsageStats::default());
+        assert_eq!(titles(&ranked), ["better"]);
+    }
+
+    #[test]
+    fn caps_results_per_plugin() {
+        let results: Vec<SearchResult> = (0..RESULTS_PER_PLUGIN + 5)
+            .map(|index| result("a", &index.to_string(), 0.5))
+            .chain([result("b", "other", 0.1)])
+            .collect();
+        let ranked = rank("q", &results, &UsageStats::default());
+        assert_eq!(ranked.len(), RESULTS_PER_PLUGIN + 1);
+        assert_eq!(titles(&ranked).last(), Some(&"other"));
+    }
+
+    #[test]
+    fn usage_boosts_picked_results() {
+        let mut usage = UsageStats::default();
+        let picked = result("a", "picked", 0.5);
+        usage.record(&picked);
+        let ranked = rank("q", &[result("a", "other", 0.5), picked], &usage);
+        assert_eq!(titles(&ranked), ["picked", "other"]);
+    }
+}